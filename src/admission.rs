@@ -0,0 +1,138 @@
+//! Small structures used to decide whether entries are admitted to a cache.
+//!
+//! Admission is based on TinyLFU, which tracks the approximate frequency of
+//! access to keys using a count-min sketch. When a bounded cache is full, a
+//! new entry is only admitted if its key has been accessed more frequently
+//! than the entry which would be evicted to make room for it. This prevents
+//! one-off scans of keys from flushing popular entries out of the cache.
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard};
+
+/// Number of rows (and therefore hashes) tracked per key in the sketch.
+const SKETCH_DEPTH: usize = 4;
+
+/// Minimum number of counters per row, to limit collisions in small caches.
+const SKETCH_MINIMUM_WIDTH: usize = 64;
+
+/// Maximum value of any single counter inside the sketch.
+const SKETCH_MAXIMUM: u8 = 15;
+
+/// Seeds used to derive a distinct hash for each row of the sketch.
+const SKETCH_SEEDS: [u64; SKETCH_DEPTH] = [
+    0x9E37_79B9_7F4A_7C15,
+    0xC2B2_AE3D_27D4_EB4F,
+    0x1656_67B1_9E37_79F9,
+    0x85EB_CA77_C2B2_AE63,
+];
+
+/// Admission filter used to provide scan resistance to a bounded cache.
+///
+/// Keys are hashed via a function pointer rather than a bound on the cache,
+/// so that only caches with admission enabled require `K: Hash`.
+pub(crate) struct Admission<K> {
+    hasher: fn(&K) -> u64,
+    sketch: Mutex<FrequencySketch>,
+}
+
+impl<K> Admission<K> {
+    /// Create a new admission filter for a cache of the provided capacity.
+    pub fn new(capacity: usize) -> Self
+    where
+        K: Hash,
+    {
+        Self {
+            hasher: hash::<K>,
+            sketch: Mutex::new(FrequencySketch::new(capacity)),
+        }
+    }
+
+    /// Determine whether a candidate key should replace a victim key.
+    ///
+    /// Candidates are only admitted if they are strictly more frequent.
+    pub fn admit(&self, candidate: &K, victim: &K) -> bool {
+        let sketch = self.sketch();
+        sketch.estimate((self.hasher)(candidate)) > sketch.estimate((self.hasher)(victim))
+    }
+
+    /// Record an access to a key.
+    pub fn record(&self, key: &K) {
+        self.sketch().increment((self.hasher)(key));
+    }
+
+    /// Lock the sketch, ignoring any poisoning.
+    fn sketch(&self) -> MutexGuard<'_, FrequencySketch> {
+        self.sketch.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Count-min sketch used to estimate the frequency of access to keys.
+///
+/// Counters saturate at a small maximum, and all counters are halved once
+/// a number of increments proportional to the capacity has been recorded,
+/// so that the popularity of keys fades over time.
+struct FrequencySketch {
+    table: Vec<u8>,
+    width: usize,
+    additions: usize,
+    period: usize,
+}
+
+impl FrequencySketch {
+    /// Create a new sketch sized for a cache of the provided capacity.
+    fn new(capacity: usize) -> Self {
+        let width = capacity.max(SKETCH_MINIMUM_WIDTH).next_power_of_two();
+        Self {
+            table: vec![0; width * SKETCH_DEPTH],
+            width,
+            additions: 0,
+            period: width.saturating_mul(10),
+        }
+    }
+
+    /// Estimate the frequency of a hash, as the minimum of its counters.
+    fn estimate(&self, hash: u64) -> u8 {
+        (0..SKETCH_DEPTH)
+            .map(|row| self.table[self.index(row, hash)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Increment the counters of a hash, aging the sketch when required.
+    fn increment(&mut self, hash: u64) {
+        for row in 0..SKETCH_DEPTH {
+            let idx = self.index(row, hash);
+            if self.table[idx] < SKETCH_MAXIMUM {
+                self.table[idx] += 1;
+            }
+        }
+
+        self.additions += 1;
+
+        if self.additions >= self.period {
+            self.age();
+        }
+    }
+
+    /// Halve all counters inside the sketch.
+    fn age(&mut self) {
+        for counter in self.table.iter_mut() {
+            *counter /= 2;
+        }
+        self.additions /= 2;
+    }
+
+    /// Calculate the index of the counter for a hash in a row.
+    fn index(&self, row: usize, hash: u64) -> usize {
+        let mixed = hash.wrapping_mul(SKETCH_SEEDS[row]);
+        let column = (mixed >> 32) as usize & (self.width - 1);
+        row * self.width + column
+    }
+}
+
+/// Hash a key using the default hasher.
+fn hash<K: Hash>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
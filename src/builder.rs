@@ -0,0 +1,219 @@
+//! Builder structures used to configure caches before construction.
+//!
+//! The `CacheBuilder` type gathers every configuration option for a `Cache`
+//! in a single place, before producing the cache via `CacheBuilder::build`.
+//! Unlike the `with_*` methods on `Cache` (which remain available for simple
+//! cases), the configuration is validated before the cache is constructed.
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::hash::Hash;
+use std::time::Duration;
+
+use crate::cache::Cache;
+use crate::eviction::{EvictionCause, EvictionPolicy};
+
+/// Builder used to configure and construct a `Cache`.
+///
+/// Each setter mirrors the `with_*` method of the same name on `Cache`, so
+/// see the documentation of those methods for further details.
+pub struct CacheBuilder<K, V> {
+    cache: Cache<K, V>,
+    capacity: Option<usize>,
+    weight: Option<usize>,
+    weigher: bool,
+}
+
+/// Errors raised when validating the configuration of a `CacheBuilder`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// A maximum capacity of `0` was provided.
+    ZeroCapacity,
+    /// A maximum weight of `0` was provided.
+    ZeroWeight,
+    /// A maximum weight was provided without a weigher.
+    MissingWeigher,
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BuildError::ZeroCapacity => "maximum capacity must be greater than zero",
+            BuildError::ZeroWeight => "maximum weight must be greater than zero",
+            BuildError::MissingWeigher => "maximum weight requires a weigher",
+        })
+    }
+}
+
+impl Error for BuildError {}
+
+impl<K, V> CacheBuilder<K, V>
+where
+    K: Ord + Clone,
+{
+    /// Construct a new `CacheBuilder` with the default configuration.
+    pub fn new() -> Self {
+        Self {
+            cache: Cache::new(),
+            capacity: None,
+            weight: None,
+            weigher: false,
+        }
+    }
+
+    /// Sets the maximum number of entries allowed inside the cache.
+    pub fn max_capacity(mut self, cap: usize) -> Self {
+        self.capacity = Some(cap);
+        self.map(|cache| cache.with_max_capacity(cap))
+    }
+
+    /// Sets the maximum number of entries allowed inside the cache, evicting the
+    /// entries which expire soonest.
+    pub fn max_entries(mut self, cap: usize) -> Self {
+        self.capacity = Some(cap);
+        self.map(|cache| cache.with_max_entries(cap))
+    }
+
+    /// Sets the maximum number of entries allowed inside the cache, with admission.
+    pub fn tinylfu(mut self, cap: usize) -> Self
+    where
+        K: Hash,
+    {
+        self.capacity = Some(cap);
+        self.map(|cache| cache.with_tinylfu(cap))
+    }
+
+    /// Sets the maximum total weight of entries allowed inside the cache.
+    pub fn max_weight(mut self, weight: usize) -> Self {
+        self.weight = Some(weight);
+        self.map(|cache| cache.with_max_weight(weight))
+    }
+
+    /// Sets the function used to calculate the weight of entries in the cache.
+    pub fn weigher<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &V) -> usize + Send + Sync + 'static,
+    {
+        self.weigher = true;
+        self.map(|cache| cache.with_weigher(f))
+    }
+
+    /// Sets a seed for the random number generator used when purging the cache.
+    pub fn rng_seed(self, seed: u64) -> Self {
+        self.map(|cache| cache.with_rng_seed(seed))
+    }
+
+    /// Sets the policy used to select the entries removed when purging the cache.
+    pub fn eviction_policy<P>(self, policy: P) -> Self
+    where
+        P: EvictionPolicy<K, V> + 'static,
+    {
+        self.map(|cache| cache.with_eviction_policy(policy))
+    }
+
+    /// Enables sliding expiration for entries inside the cache.
+    pub fn sliding_expiration(self) -> Self {
+        self.map(|cache| cache.with_sliding_expiration())
+    }
+
+    /// Enables re-arming the expiration of entries on read inside the cache.
+    pub fn touch_on_read(self, ttl: Duration) -> Self {
+        self.map(|cache| cache.with_touch_on_read(ttl))
+    }
+
+    /// Sets a maximum random offset to add to the expiration of inserted entries.
+    pub fn expiry_jitter(self, max: Duration) -> Self {
+        self.map(|cache| cache.with_expiry_jitter(max))
+    }
+
+    /// Enables precise expiration for entries inside the cache.
+    pub fn precise_expiration(self) -> Self {
+        self.map(|cache| cache.with_precise_expiration())
+    }
+
+    /// Sets a listener to be notified of entries being evicted from the cache.
+    pub fn eviction_listener<F>(self, f: F) -> Self
+    where
+        F: Fn(&K, &V, EvictionCause) + Send + Sync + 'static,
+    {
+        self.map(|cache| cache.with_eviction_listener(f))
+    }
+
+    /// Sets a listener to take ownership of entries dropped by the cache.
+    pub fn eviction_listener_owned<F>(self, f: F) -> Self
+    where
+        F: Fn(K, V, EvictionCause) + Send + Sync + 'static,
+    {
+        self.map(|cache| cache.with_eviction_listener_owned(f))
+    }
+
+    /// Sets a hook to be called for every entry inserted into the cache.
+    pub fn on_insert<F>(self, f: F) -> Self
+    where
+        F: Fn(&K, &V) + Send + Sync + 'static,
+    {
+        self.map(|cache| cache.with_on_insert(f))
+    }
+
+    /// Sets a hook to be called for every entry removed from the cache.
+    pub fn on_remove<F>(self, f: F) -> Self
+    where
+        F: Fn(&K, &V) + Send + Sync + 'static,
+    {
+        self.map(|cache| cache.with_on_remove(f))
+    }
+
+    /// Sets a listener to be notified when the cache grows past a high water mark.
+    pub fn high_water_mark<F>(self, count: usize, f: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.map(|cache| cache.with_high_water_mark(count, f))
+    }
+
+    /// Sets a channel to receive entries removed from the cache after expiring.
+    #[cfg(feature = "async-channel")]
+    pub fn expired_channel(self, capacity: usize) -> Self {
+        self.map(|cache| cache.with_expired_channel(capacity))
+    }
+
+    /// Sets the label inside the cache for logging purposes.
+    pub fn label(self, s: &str) -> Self {
+        self.map(|cache| cache.with_label(s))
+    }
+
+    /// Construct the configured `Cache`, validating the configuration.
+    ///
+    /// Bounds which are explicitly set to `0` are rejected (rather than treated as
+    /// unbounded, as with the `with_*` methods on `Cache`), as is a maximum weight
+    /// without a weigher to calculate the weight of entries.
+    pub fn build(self) -> Result<Cache<K, V>, BuildError> {
+        if self.capacity == Some(0) {
+            return Err(BuildError::ZeroCapacity);
+        }
+
+        match self.weight {
+            Some(0) => Err(BuildError::ZeroWeight),
+            Some(_) if !self.weigher => Err(BuildError::MissingWeigher),
+            _ => Ok(self.cache),
+        }
+    }
+
+    /// Apply a configuration change to the inner cache.
+    fn map<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(Cache<K, V>) -> Cache<K, V>,
+    {
+        self.cache = f(self.cache);
+        self
+    }
+}
+
+/// Default implementation.
+impl<K, V> Default for CacheBuilder<K, V>
+where
+    K: Ord + Clone,
+{
+    fn default() -> Self {
+        CacheBuilder::new()
+    }
+}
@@ -8,22 +8,55 @@
 //! The eviction algorithm has been based on Redis, and essentially just samples
 //! the entry set on an interval to prune the inner tree over time. More information
 //! on how this works can be seen on the `monitor` method of the `Cache` type.
+use std::borrow::Borrow;
 use std::cmp;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::convert::Infallible;
+use std::future::{self, Future};
+use std::hash::Hash;
+use std::iter::FromIterator;
 use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as SyncMutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
 use std::time::{Duration, Instant};
 
-use async_lock::{RwLock, RwLockUpgradableReadGuard};
-use async_timer::Interval;
+#[cfg(feature = "async-channel")]
+use async_channel::{Receiver, TrySendError};
+use async_lock::{Mutex, RwLock, RwLockUpgradableReadGuard, RwLockWriteGuard};
+use futures_lite::{stream, Stream};
 use log::{debug, log_enabled, trace, Level};
-use rand::prelude::*;
+use rand::seq::index;
+use rand::Rng;
+#[cfg(feature = "serde")]
+use serde::{ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::entry::{CacheEntry, CacheExpiration, CacheReadGuard};
+use crate::admission::Admission;
+use crate::builder::CacheBuilder;
+use crate::entry::{CacheEntry, CacheExpiration, CacheLoadGuard, CacheReadGuard, CacheWriteGuard};
+use crate::events::{CacheEvent, EventStream, Subscribers};
+#[cfg(feature = "async-channel")]
+use crate::eviction::ExpiredChannel;
+use crate::eviction::{
+    EvictionCause, EvictionListener, EvictionPolicy, Hook, OwnedEvictionListener, RandomSampling,
+    StoreView, WaterMarkListener, Weigher,
+};
+use crate::stats::{CacheCounters, CacheStats, PurgeReport};
+use crate::timer::{self, Interval};
+
+// Number of entries copied from the store per lock acquisition when streaming.
+const STREAM_CHUNK_SIZE: usize = 64;
+
+// Number of entries sampled when selecting an entry to evict from a bounded cache.
+const VICTIM_SAMPLE_SIZE: usize = 8;
 
 // Define small private macro to unpack entry references.
 macro_rules! unpack {
     ($entry: expr) => {
-        if $entry.expiration().is_expired() {
+        if $entry.is_expired() {
             None
         } else {
             Some($entry)
@@ -38,6 +71,34 @@ macro_rules! unpack {
 /// handle - which is what would happen with standard locking implementations.
 pub struct Cache<K, V> {
     store: RwLock<BTreeMap<K, CacheEntry<V>>>,
+    loading: SyncMutex<BTreeMap<K, Arc<Mutex<()>>>>,
+    waiting: SyncMutex<BTreeMap<K, Vec<Arc<Waiter>>>>,
+    policy: Box<dyn EvictionPolicy<K, V>>,
+    admission: Option<Admission<K>>,
+    capacity: usize,
+    soonest: bool,
+    weight: AtomicUsize,
+    size: AtomicUsize,
+    stale: AtomicUsize,
+    max_weight: usize,
+    weigher: Option<Weigher<K, V>>,
+    clock: AtomicU64,
+    sliding: bool,
+    rearm: Option<Duration>,
+    jitter: Option<Duration>,
+    deadlines: Option<SyncMutex<BTreeSet<(Instant, K)>>>,
+    counters: CacheCounters,
+    purged: SyncMutex<Option<PurgeReport>>,
+    listener: Option<EvictionListener<K, V>>,
+    owned_listener: Option<OwnedEvictionListener<K, V>>,
+    on_insert: Option<Hook<K, V>>,
+    on_remove: Option<Hook<K, V>>,
+    high_water: Option<(usize, WaterMarkListener)>,
+    subscribers: Subscribers<K>,
+    #[cfg(feature = "async-channel")]
+    expired: Option<ExpiredChannel<K, V>>,
+    #[cfg(feature = "async-channel")]
+    dropped: AtomicU64,
     label: String,
 }
 
@@ -49,255 +110,3386 @@ where
     pub fn new() -> Self {
         Self {
             store: RwLock::new(BTreeMap::new()),
+            loading: SyncMutex::new(BTreeMap::new()),
+            waiting: SyncMutex::new(BTreeMap::new()),
+            policy: Box::new(RandomSampling::new()),
+            admission: None,
+            capacity: 0,
+            soonest: false,
+            weight: AtomicUsize::new(0),
+            size: AtomicUsize::new(0),
+            stale: AtomicUsize::new(0),
+            max_weight: 0,
+            weigher: None,
+            clock: AtomicU64::new(0),
+            sliding: false,
+            rearm: None,
+            jitter: None,
+            deadlines: None,
+            counters: CacheCounters::default(),
+            purged: SyncMutex::new(None),
+            listener: None,
+            owned_listener: None,
+            on_insert: None,
+            on_remove: None,
+            high_water: None,
+            subscribers: Subscribers::new(),
+            #[cfg(feature = "async-channel")]
+            expired: None,
+            #[cfg(feature = "async-channel")]
+            dropped: AtomicU64::new(0),
             label: "".to_owned(),
         }
     }
 
-    /// Sets the label inside this cache for logging purposes.
-    pub fn with_label(mut self, s: &str) -> Self {
-        self.label = format!("cache({}): ", s);
+    /// Construct a new `Cache` intended to hold the provided number of entries.
+    ///
+    /// This is a no-op which exists only for parity with the `with_capacity`
+    /// constructors of the standard collections; the entries of a cache are stored
+    /// in a `BTreeMap`, which cannot allocate ahead of time, so the capacity is
+    /// ignored entirely and this is equivalent to `Cache::new`. Note that this
+    /// does *not* bound the cache; for that, see `Cache::with_max_capacity`.
+    pub fn with_capacity(_capacity: usize) -> Self {
+        Self::new()
+    }
+
+    /// Construct a new `CacheBuilder` to configure a `Cache`.
+    pub fn builder() -> CacheBuilder<K, V> {
+        CacheBuilder::new()
+    }
+
+    /// Sets the maximum number of entries allowed inside this cache.
+    ///
+    /// When an insertion of a new key would grow the cache beyond this capacity,
+    /// an entry is evicted to make room. Rather than walking the whole cache, a
+    /// random sample of 8 entries is inspected (plus one more, in case the new key
+    /// is sampled), and the least recently used entry of the sample is evicted
+    /// (although expired entries in the sample are always evicted first).
+    ///
+    /// This approximates LRU eviction, and is only exact whilst the cache holds no
+    /// more than 9 entries; in a larger cache, a recently used entry can still be
+    /// evicted if every other entry in the sample was used more recently. A
+    /// capacity of `0` means unbounded, which is the default.
+    pub fn with_max_capacity(mut self, cap: usize) -> Self {
+        self.capacity = cap;
+        self.soonest = false;
         self
     }
 
-    /// Remove all entries from the cache.
-    pub async fn clear(&self) {
-        self.store.write().await.clear()
+    /// Sets the maximum number of entries allowed inside this cache, evicting the
+    /// entries which expire soonest.
+    ///
+    /// This behaves like `Cache::with_max_capacity`, except that the entry of the
+    /// sample which would expire soonest is evicted rather than the least recently
+    /// used (expired entries are still always evicted first). Entries without an
+    /// expiration are only evicted once there are no others in the sample, with
+    /// ties broken by evicting the least recently used entry. A capacity of `0`
+    /// means unbounded, which is the default.
+    pub fn with_max_entries(mut self, cap: usize) -> Self {
+        self.capacity = cap;
+        self.soonest = true;
+        self
     }
 
-    /// Retrieve the number of expired entries inside the cache.
+    /// Sets the maximum number of entries allowed inside this cache, with admission.
     ///
-    /// Note that this is calculated by walking the set of entries and
-    /// should therefore not be used in performance sensitive situations.
-    pub async fn expired(&self) -> usize {
-        self.store
-            .read()
-            .await
-            .iter()
-            .filter(|(_, entry)| entry.expiration().is_expired())
-            .count()
+    /// This behaves like `Cache::with_max_capacity`, except that the cache also tracks
+    /// the approximate frequency of access to keys (via a TinyLFU sketch, which fades
+    /// over time). When the cache is full, a new key is only admitted if it has been
+    /// accessed more frequently than the entry selected for eviction; otherwise the new
+    /// entry is rejected, and reported to listeners as `EvictionCause::Capacity`. This
+    /// prevents one-off scans of keys from flushing popular entries out of the cache.
+    ///
+    /// Accesses are recorded for lookups of keys present in the cache, and for every
+    /// insertion; as lookups only have a borrowed form of the key, a lookup of a key
+    /// which is absent can't be recorded, so inserting an absent key also counts as
+    /// the miss which (typically) preceded it.
+    ///
+    /// Entries loaded via `Cache::get_or_insert_with` (and friends) are also subject
+    /// to admission; if rejected, the loaded value is still returned to the caller
+    /// (as `CacheLoadGuard::Rejected`), but it is not stored inside the cache.
+    pub fn with_tinylfu(mut self, cap: usize) -> Self
+    where
+        K: Hash,
+    {
+        self.capacity = cap;
+        self.admission = Some(Admission::new(cap));
+        self
     }
 
-    /// Retrieve a reference to a value inside the cache.
+    /// Sets the maximum total weight of entries allowed inside this cache.
     ///
-    /// The returned reference is bound inside a `RwLockReadGuard`.
-    pub async fn get(&self, k: &K) -> Option<CacheReadGuard<'_, V>> {
-        let guard = self.store.read().await;
-        let found = guard.get(k)?;
-        let valid = unpack!(found)?;
+    /// When an insertion would grow the total weight of the cache beyond this
+    /// bound, entries are evicted until the new entry fits, selected in the same
+    /// (approximately least recently used) way as `Cache::with_max_capacity`. An
+    /// entry which is heavier than this bound will still be inserted, once all
+    /// other entries have been evicted. A maximum weight of `0` means unbounded.
+    ///
+    /// Entries are weighed using the weigher provided to `Cache::with_weigher`,
+    /// or weigh `1` each if no weigher has been provided. The running total is
+    /// available via `Cache::weight`.
+    pub fn with_max_weight(mut self, weight: usize) -> Self {
+        self.max_weight = weight;
+        self
+    }
 
-        Some(CacheReadGuard {
-            entry: valid,
-            marker: PhantomData,
-        })
+    /// Sets the function used to calculate the weight of entries in this cache.
+    ///
+    /// The weight of an entry is calculated when it's inserted, and re-calculated
+    /// after each modification via `Cache::update` (or any of the update methods).
+    /// Modifications made through `Cache::get_mut` are not re-weighed. Weights are
+    /// only used to bound the cache via `Cache::with_max_weight`.
+    ///
+    /// Plain insertions (`Cache::insert`, `Cache::insert_many` and friends) weigh new
+    /// entries before taking the cache lock, so that expensive weighers do not block
+    /// other callers. Operations which must inspect the cache before creating an
+    /// entry (such as `Cache::compute`, `Cache::insert_if_absent`, `Cache::replace`,
+    /// `Cache::merge` and `Cache::get_or_insert_with`) weigh entries whilst holding
+    /// the lock, as does `Cache::insert_nowait`.
+    pub fn with_weigher<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &V) -> usize + Send + Sync + 'static,
+    {
+        self.weigher = Some(Box::new(f));
+        self
     }
 
-    /// Retrieve the number of entries inside the cache.
+    /// Sets a seed for the random number generator used when purging this cache.
     ///
-    /// This *does* include entries which may be expired but are not yet evicted. In
-    /// future there may be an API addition to find the unexpired count, but as it's
-    /// relatively expensive it has been omitted for the time being.
-    pub async fn len(&self) -> usize {
-        self.store.read().await.len()
+    /// By default entries are sampled using a thread local generator, and so are
+    /// fully random. Providing a seed means that, given the same cache state, each
+    /// purge will sample the same entries; this is mainly useful for testing.
+    ///
+    /// This replaces any policy set via `Cache::with_eviction_policy` with a seeded
+    /// `RandomSampling` policy.
+    pub fn with_rng_seed(mut self, seed: u64) -> Self {
+        self.policy = Box::new(RandomSampling::seeded(seed));
+        self
     }
 
-    /// Insert a key/value pair into the cache with an associated expiration.
+    /// Sets the policy used to select the entries removed when purging this cache.
     ///
-    /// The third argument controls expiration, which can be provided using any type which
-    /// implements `Into<CacheExpiration>`. This allows for various different syntax based
-    /// on your use case. If you do not want expiration, use `CacheExpiration::none()`.
-    pub async fn insert<E>(&self, k: K, v: V, e: E) -> Option<V>
+    /// By default entries are selected by `RandomSampling`, which removes expired
+    /// entries from a random sample of each batch. Custom policies may also select
+    /// unexpired entries, which are reported to listeners as `EvictionCause::Capacity`.
+    /// Policies are not used when precise expiration is enabled.
+    pub fn with_eviction_policy<P>(mut self, policy: P) -> Self
     where
-        E: Into<CacheExpiration>,
+        P: EvictionPolicy<K, V> + 'static,
     {
-        let entry = CacheEntry::new(v, e.into());
-        self.store
-            .write()
-            .await
-            .insert(k, entry)
-            .and_then(|entry| unpack!(entry))
-            .map(CacheEntry::into_inner)
+        self.policy = Box::new(policy);
+        self
     }
 
-    /// Check whether the cache is empty.
-    pub async fn is_empty(&self) -> bool {
-        self.store.read().await.is_empty()
+    /// Enables sliding expiration for entries inside this cache.
+    ///
+    /// With sliding expiration enabled, every successful `Cache::get` will push
+    /// the expiration of the entry forward by the duration it was inserted with.
+    /// Entries inserted with an `Instant` or `CacheExpiration::none()` will not
+    /// be affected. Note that reads will require a write lock in this mode.
+    pub fn with_sliding_expiration(mut self) -> Self {
+        self.sliding = true;
+        self
     }
 
-    /// Retrieve a `Future` used to monitor expired keys.
+    /// Enables re-arming the expiration of entries on read inside this cache.
     ///
-    /// This future must be spawned on whatever runtime you are using inside your
-    /// application; not doing this will result in keys never being expired.
+    /// This is a variant of sliding expiration, where every successful `Cache::get`
+    /// will reset the expiration of the entry to the provided `ttl` from now, rather
+    /// than the duration it was inserted with (so entries inserted with an `Instant`
+    /// are affected too). Entries inserted without an expiration (including those
+    /// with only an idle timeout) will not be affected. As with sliding expiration,
+    /// reads will require a write lock in this mode.
+    pub fn with_touch_on_read(mut self, ttl: Duration) -> Self {
+        self.sliding = true;
+        self.rearm = Some(ttl);
+        self
+    }
+
+    /// Sets a maximum random offset to add to the expiration of inserted entries.
     ///
-    /// For expiration logic, please see `Cache::purge`, as this is used under the hood.
-    pub async fn monitor(&self, sample: usize, threshold: f64, frequency: Duration) {
-        let mut interval = Interval::platform_new(frequency);
-        loop {
-            interval.as_mut().await;
-            self.purge(sample, threshold).await;
-        }
+    /// Every entry inserted with an expiration will have its expiration pushed back
+    /// by a random offset in the range `[0, max)`. This spreads out the expiration of
+    /// entries inserted at the same time with the same expiration, to avoid spikes
+    /// in the purge loop (and downstream). Entries inserted without an expiration
+    /// are not affected.
+    pub fn with_expiry_jitter(mut self, max: Duration) -> Self {
+        self.jitter = Some(max);
+        self
     }
 
-    /// Cleanses the cache of expired entries.
+    /// Enables precise expiration for entries inside this cache.
     ///
-    /// Keys are expired using the same logic as the popular caching system Redis:
+    /// With precise expiration enabled, the cache maintains an index of entries
+    /// ordered by deadline, and `Cache::purge` will remove entries in the order
+    /// they expire rather than sampling entries at random. This means expired
+    /// entries are removed promptly, at the cost of the memory for the index.
     ///
-    /// 1. Wait until the next tick of `frequency`.
-    /// 2. Take a sample of `sample` keys from the cache.
-    /// 3. Remove any expired keys from the sample.
-    /// 4. Based on `threshold` percentage:
-    ///     4a. If more than `threshold` were expired, goto #2.
-    ///     4b. If less than `threshold` were expired, goto #1.
+    /// In this mode the `sample` passed to `Cache::purge` is used as the number of
+    /// entries to remove per lock, and the `threshold` is ignored. Deadlines which
+    /// are pushed back (via sliding expiration or idle timeouts) are re-indexed
+    /// lazily, once their original deadline has passed.
+    pub fn with_precise_expiration(mut self) -> Self {
+        self.deadlines = Some(SyncMutex::new(BTreeSet::new()));
+        self
+    }
+
+    /// Sets a listener to be notified of entries being evicted from this cache.
     ///
-    /// This means that at any point you may have up to `threshold` percent of your
-    /// cache storing expired entries (assuming the monitor just ran), so make sure
-    /// to tune your frequency, sample size, and threshold accordingly.
-    pub async fn purge(&self, sample: usize, threshold: f64) {
-        let start = Instant::now();
+    /// The listener is called for entries removed by `Cache::purge`, entries which
+    /// are manually removed, entries replaced by an insertion, entries evicted due
+    /// to capacity, and entries removed by `Cache::clear`. Listeners are called
+    /// after the cache lock is released.
+    pub fn with_eviction_listener<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &V, EvictionCause) + Send + Sync + 'static,
+    {
+        self.listener = Some(Box::new(f));
+        self
+    }
 
-        let mut locked = Duration::from_nanos(0);
-        let mut removed = 0;
+    /// Sets a listener to take ownership of entries dropped by this cache.
+    ///
+    /// Unlike `Cache::with_eviction_listener`, this listener is only called for
+    /// entries which the cache would otherwise drop: entries removed by `Cache::purge`,
+    /// `Cache::clear` and `Cache::retain` (and similar), and entries evicted due to
+    /// capacity. The listener is not called for:
+    ///
+    /// * entries explicitly removed via `Cache::remove` (and similar), as the
+    ///   removed value is handed back to the caller.
+    /// * unexpired values replaced by an insertion, such as via `Cache::insert`
+    ///   (which hands the replaced value back to the caller).
+    /// * expired entries, if a channel has been set via `Cache::with_expired_channel`,
+    ///   as these are published to the channel instead.
+    ///
+    /// Listeners are called after the cache lock is released.
+    pub fn with_eviction_listener_owned<F>(mut self, f: F) -> Self
+    where
+        F: Fn(K, V, EvictionCause) + Send + Sync + 'static,
+    {
+        self.owned_listener = Some(Box::new(f));
+        self
+    }
 
-        loop {
-            // lock the store and grab a generator
-            let store = self.store.upgradable_read().await;
+    /// Sets a hook to be called for every entry inserted into this cache.
+    ///
+    /// The hook is called for every insertion, including those which replace an
+    /// existing entry and those which are then rejected due to capacity (which are
+    /// passed to the `Cache::with_on_remove` hook straight away). The hook is called
+    /// before the entry is stored; plain insertions (such as `Cache::insert` and
+    /// `Cache::insert_many`) call it before taking the cache lock. Insertions which
+    /// depend on the current entry (`Cache::compute`, `Cache::insert_if_absent`,
+    /// `Cache::update_or_insert`, `Cache::get_or_insert_with` and `Cache::merge`)
+    /// and `Cache::insert_nowait` call it *whilst the write lock is held*, so it
+    /// should be kept as cheap as possible.
+    pub fn with_on_insert<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &V) + Send + Sync + 'static,
+    {
+        self.on_insert = Some(Box::new(f));
+        self
+    }
 
-            // once we're empty, no point carrying on
-            if store.is_empty() {
-                break;
-            }
+    /// Sets a hook to be called for every entry removed from this cache.
+    ///
+    /// The hook is called for every entry which leaves the cache, however it is
+    /// removed (including replaced and drained entries). Paired with the hook set
+    /// via `Cache::with_on_insert`, this allows tracking the number of entries in
+    /// the cache externally without drifting. This hook is called after the cache
+    /// lock is released, with the exception of conflicts during `Cache::merge`.
+    /// A panicking hook is propagated to the caller, but the cache remains usable.
+    pub fn with_on_remove<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &V) + Send + Sync + 'static,
+    {
+        self.on_remove = Some(Box::new(f));
+        self
+    }
 
-            // determine the sample size of the batch
-            let total = store.len();
-            let sample = cmp::min(sample, total);
+    /// Sets a listener to be notified when this cache grows past a high water mark.
+    ///
+    /// The listener is called with the number of entries inside the cache after any
+    /// insertion which grows the cache past the provided number of entries. This is
+    /// advisory only, and is called after the cache lock has been released.
+    pub fn with_high_water_mark<F>(mut self, count: usize, f: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.high_water = Some((count, Box::new(f)));
+        self
+    }
 
-            // counter to track removed keys
-            let mut gone = 0;
+    /// Sets a bounded channel to receive entries removed from this cache after expiring.
+    ///
+    /// Expired entries removed from the cache are sent to the channel, whether they
+    /// are removed by `Cache::purge`, by an insertion, or in passing by any of the
+    /// removal methods. The only exceptions are methods which hand expired entries
+    /// back to the caller, such as `Cache::drain_expired` and `Cache::drain_unfiltered`.
+    ///
+    /// The channel can be consumed via `Cache::expired_receiver`, which allows
+    /// expirations to be processed on another task at whatever pace is required.
+    /// If the channel is full, expired entries are dropped rather than sent; the
+    /// number of dropped entries is available via `Cache::expired_dropped`. The
+    /// channel always has a capacity of at least `1`.
+    #[cfg(feature = "async-channel")]
+    pub fn with_expired_channel(mut self, capacity: usize) -> Self {
+        self.expired = Some(async_channel::bounded(cmp::max(capacity, 1)));
+        self
+    }
 
-            // create our temporary key store and index tree
-            let mut keys = Vec::with_capacity(sample);
-            let mut indices: BTreeSet<usize> = BTreeSet::new();
+    /// Sets the label inside this cache for logging purposes.
+    pub fn with_label(mut self, s: &str) -> Self {
+        self.label = format!("cache({}): ", s);
+        self
+    }
 
-            {
-                // fetch `sample` keys at random
-                let mut rng = rand::thread_rng();
-                while indices.len() < sample {
-                    indices.insert(rng.gen_range(0..total));
-                }
+    /// Remove all entries from the cache.
+    ///
+    /// Removed entries are dropped; to take ownership of them instead, use either
+    /// `Cache::drain` or `Cache::drain_unfiltered`. Any listener is notified of the
+    /// removed entries once the lock has been released.
+    pub async fn clear(&self) {
+        let cleared = self.take_all().await;
+
+        if self.observed() {
+            for (key, entry) in &cleared {
+                self.notify(key, entry, EvictionCause::Cleared);
             }
+        }
 
-            {
-                // tracker for previous index
-                let mut prev = 0;
+        self.discard(cleared, EvictionCause::Cleared);
+    }
 
-                // boxed iterator to allow us to iterate a single time for all indices
-                let mut iter: Box<dyn Iterator<Item = (&K, &CacheEntry<V>)>> =
-                    Box::new(store.iter());
+    /// Replace the value of an entry in the cache if it matches an expected value.
+    ///
+    /// The value is only replaced if an unexpired entry exists and the current value
+    /// is equal to `expected`; the expiration of the entry is preserved. Otherwise the
+    /// current value is returned inside an `Err`, or `None` if no unexpired entry was
+    /// found. As with `Cache::update`, no listeners are notified of the swap.
+    pub async fn compare_and_swap<B>(&self, k: &B, expected: &V, new: V) -> Result<(), Option<V>>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+        V: PartialEq + Clone,
+    {
+        let mut guard = self.store.write().await;
 
-                // walk our index list
-                for idx in indices {
-                    // calculate how much we need to shift the iterator
-                    let offset = idx
-                        .checked_sub(prev)
-                        .and_then(|idx| idx.checked_sub(1))
-                        .unwrap_or(0);
+        let (key, entry) = match Self::find_mut(&mut guard, k) {
+            Some((key, entry)) if !entry.is_expired() => (key, entry),
+            _ => return Err(None),
+        };
 
-                    // shift and mark the current index
-                    iter = Box::new(iter.skip(offset));
-                    prev = idx;
+        if entry.value() != expected {
+            return Err(Some(entry.value().clone()));
+        }
 
-                    // fetch the next pair (at our index)
-                    let (key, entry) = iter.next().unwrap();
+        *entry.value_mut() = new;
+        self.refresh(key, entry);
 
-                    // skip if not expired
-                    if !entry.expiration().is_expired() {
-                        continue;
-                    }
+        self.announce(key, CacheEvent::Updated);
+        drop(guard);
 
-                    // otherwise mark for removal
-                    keys.push(key.to_owned());
+        Ok(())
+    }
 
-                    // and increment remove count
-                    gone += 1;
-                }
-            }
+    /// Compute a new value for a key in the cache, based on the current value.
+    ///
+    /// The closure is provided with the current value, or `None` if no unexpired
+    /// entry exists. Returning `Some` will store the new value with the provided
+    /// expiration, whereas returning `None` will remove any existing entry. This is
+    /// all done under a single write lock, so no other caller can modify the entry
+    /// in between reading and writing it.
+    ///
+    /// The previous value is returned if an unexpired entry was replaced or removed.
+    pub async fn compute<E, F>(&self, k: K, e: E, f: F) -> Option<V>
+    where
+        E: Into<CacheExpiration>,
+        F: FnOnce(Option<&V>) -> Option<V>,
+    {
+        let mut store = self.store.write().await;
 
-            {
-                // upgrade to a write guard so that we can make our changes
-                let acquired = Instant::now();
-                let mut store = RwLockUpgradableReadGuard::upgrade(store).await;
+        let current = store
+            .get(&k)
+            .and_then(|entry| unpack!(entry))
+            .map(CacheEntry::value);
 
-                // remove all expired keys
-                for key in &keys {
-                    store.remove(key);
-                }
+        if let Some(v) = f(current) {
+            let entry = self.create(&k, v, e.into());
+            self.inserting(&k, &entry);
 
-                // increment the lock timer tracking directly
-                locked = locked.checked_add(acquired.elapsed()).unwrap();
-            }
+            return self
+                .insert_locked(store, k, entry)
+                .map(CacheEntry::into_inner);
+        }
 
-            // log out now many of the sampled keys were removed
-            if log_enabled!(Level::Trace) {
-                trace!(
-                    "{}removed {} / {} ({:.2}%) of the sampled keys",
-                    self.label,
-                    gone,
-                    sample,
-                    (gone as f64 / sample as f64) * 100f64,
-                );
-            }
+        let (key, entry) = store.remove_entry(&k)?;
+        self.unindex(&key, &entry);
+        self.reweigh(Some(&entry), None);
+        self.announce_removal(&key, &entry, EvictionCause::Manual);
 
-            // bump total remove count
-            removed += gone;
+        drop(store);
+        self.notify(&key, &entry, EvictionCause::Manual);
 
-            // break the loop if we don't meet thresholds
-            if (gone as f64) < (sample as f64 * threshold) {
-                break;
-            }
+        if entry.is_expired() {
+            self.release(key, entry, EvictionCause::Expired);
+            return None;
         }
 
-        // log out the completion as well as the time taken in millis
-        if log_enabled!(Level::Debug) {
-            debug!(
-                "{}purge loop removed {} entries in {:.0?} ({:.0?} locked)",
-                self.label,
-                removed,
-                start.elapsed(),
-                locked
-            );
-        }
+        Some(entry.into_inner())
     }
 
-    /// Remove an entry from the cache and return any stored value.
-    pub async fn remove(&self, k: &K) -> Option<V> {
+    /// Remove all expired entries from the cache.
+    ///
+    /// This is equivalent to `Cache::drain_expired`, except that the removed entries
+    /// are dropped rather than returned. The returned value is the number of entries
+    /// which were removed.
+    pub async fn clear_expired(&self) -> usize {
+        let removed = self
+            .remove_where(EvictionCause::Expired, |_, entry| entry.is_expired())
+            .await;
+
+        let count = removed.len();
+
+        self.counters.evicted(count as u64);
+        self.discard(removed, EvictionCause::Expired);
+
+        count
+    }
+
+    /// Check whether an unexpired entry exists for a key in the cache.
+    ///
+    /// Entries which have expired but have not yet been evicted are treated
+    /// as absent, matching the behaviour of `Cache::get`.
+    pub async fn contains_key<B>(&self, k: &B) -> bool
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
         self.store
-            .write()
+            .read()
             .await
-            .remove(k)
+            .get(k)
             .and_then(|entry| unpack!(entry))
-            .map(CacheEntry::into_inner)
+            .is_some()
     }
 
-    /// Retrieve the number of unexpired entries inside the cache.
+    /// Check whether the entry for a key in the cache has expired.
+    ///
+    /// This returns `None` if no entry exists for the key, otherwise whether the
+    /// entry has expired. Unlike `Cache::contains_key`, expired entries which have
+    /// not yet been evicted are reported, which is useful to diagnose purge lag.
+    pub async fn is_expired<B>(&self, k: &B) -> Option<bool>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        self.store.read().await.get(k).map(CacheEntry::is_expired)
+    }
+
+    /// Remove all unexpired entries from the cache, returning them to the caller.
+    ///
+    /// The inner store is swapped for an empty store under the write lock, so the
+    /// lock is only held briefly regardless of the size of the cache. Entries which
+    /// have expired are dropped; use `Cache::drain_unfiltered` to include them.
+    pub async fn drain(&self) -> Vec<(K, V)> {
+        let drained = self.take_all().await;
+
+        self.drained(&drained);
+
+        let (expired, drained): (Vec<_>, Vec<_>) = drained
+            .into_iter()
+            .partition(|(_, entry)| entry.is_expired());
+
+        self.discard(expired, EvictionCause::Expired);
+
+        drained
+            .into_iter()
+            .map(|(key, entry)| (key, entry.into_inner()))
+            .collect()
+    }
+
+    /// Remove all entries from the cache, returning them alongside their expiration.
+    ///
+    /// This *does* include entries which may be expired but are not yet evicted.
+    /// As with `Cache::drain`, the lock is only held briefly to swap the store.
+    pub async fn drain_unfiltered(&self) -> Vec<(K, V, CacheExpiration)> {
+        let drained = self.take_all().await;
+
+        self.drained(&drained);
+
+        drained
+            .into_iter()
+            .map(|(key, entry)| {
+                let (value, expiration) = entry.into_parts();
+                (key, value, expiration)
+            })
+            .collect()
+    }
+
+    /// Remove all expired entries from the cache, returning them to the caller.
+    ///
+    /// Unlike `Cache::purge`, this walks every entry inside the cache under a single
+    /// write lock rather than sampling, so every expired entry will be removed. This
+    /// can be used in place of `Cache::monitor` when access to the removed entries is
+    /// required (e.g. to release associated resources).
+    pub async fn drain_expired(&self) -> Vec<(K, V)> {
+        let removed = self
+            .remove_where(EvictionCause::Expired, |_, entry| entry.is_expired())
+            .await;
+
+        self.counters.evicted(removed.len() as u64);
+
+        removed
+            .into_iter()
+            .map(|(key, entry)| (key, entry.into_inner()))
+            .collect()
+    }
+
+    /// Retrieve a snapshot of all unexpired entries alongside their remaining time.
+    ///
+    /// The remaining time is calculated in the same pass as the snapshot, and will
+    /// be `None` for entries inserted without an expiration. This can be used to
+    /// reconstruct a cache elsewhere with equivalent expirations. Entries which have
+    /// expired but have not yet been evicted are excluded, matching `Cache::get`.
+    pub async fn entries(&self) -> Vec<(K, V, Option<Duration>)>
+    where
+        V: Clone,
+    {
+        self.store
+            .read()
+            .await
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired())
+            .map(|(key, entry)| {
+                let remaining = entry.remaining();
+                (key.clone(), entry.value().clone(), remaining)
+            })
+            .collect()
+    }
+
+    /// Retrieve the number of expired entries dropped due to a full channel.
+    ///
+    /// See `Cache::with_expired_channel` for further details.
+    #[cfg(feature = "async-channel")]
+    pub fn expired_dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Retrieve a receiver for entries removed from the cache after expiring.
+    ///
+    /// This returns `None` unless a channel was set via `Cache::with_expired_channel`.
+    /// Each call returns a handle to the same channel, so each expired entry will be
+    /// received by only one of the receivers.
+    #[cfg(feature = "async-channel")]
+    pub fn expired_receiver(&self) -> Option<Receiver<(K, V)>> {
+        self.expired.as_ref().map(|(_, receiver)| receiver.clone())
+    }
+
+    /// Retrieve the number of expired entries inside the cache.
     ///
     /// Note that this is calculated by walking the set of entries and
     /// should therefore not be used in performance sensitive situations.
-    pub async fn unexpired(&self) -> usize {
+    pub async fn expired(&self) -> usize {
         self.store
             .read()
             .await
             .iter()
-            .filter(|(_, entry)| !entry.expiration().is_expired())
+            .filter(|(_, entry)| entry.is_expired())
             .count()
     }
 
-    /// Updates an entry in the cache without changing the expiration.
-    pub async fn update<F>(&self, k: &K, f: F)
+    /// Retrieve an estimate of the number of expired entries inside the cache.
+    ///
+    /// Unlike `Cache::expired`, this is a cheap read of a counter and never takes
+    /// the cache lock. Entries are only counted once they have been found to be
+    /// expired by `Cache::get` and friends, so entries which have expired but have
+    /// not been accessed since are not included (and are instead counted by the
+    /// `Cache::unexpired_estimate`). The counter is corrected as entries are removed.
+    pub fn expired_estimate(&self) -> usize {
+        self.stale.load(Ordering::Relaxed)
+    }
+
+    /// Extend the expiration of an entry in the cache by a provided duration.
+    ///
+    /// The duration is added to the existing expiration of the entry; if the entry
+    /// has no expiration, the duration is applied from the current time instead.
+    /// Expired entries will not be extended, and will return `None`. If the new
+    /// expiration cannot be represented, the entry is left unchanged.
+    ///
+    /// The returned value is the time remaining before the entry will expire.
+    pub async fn extend_expiration<B>(&self, k: &B, by: Duration) -> Option<Duration>
     where
-        F: FnOnce(&mut V),
+        K: Borrow<B>,
+        B: Ord + ?Sized,
     {
         let mut guard = self.store.write().await;
-        if let Some(entry) = guard.get_mut(k).and_then(|entry| unpack!(entry)) {
-            f(entry.value_mut());
+        let (key, found) = Self::find_mut(&mut guard, k)?;
+        let valid = unpack!(found)?;
+
+        if let Some(extended) = valid.expiration().extend(by) {
+            valid.set_expiration(extended);
+            self.index(key, valid);
+        }
+
+        valid.remaining()
+    }
+
+    /// Retrieve a reference to a value inside the cache.
+    ///
+    /// The returned reference is bound inside a `RwLockReadGuard`, which also
+    /// provides access to the expiration and remaining time of the entry.
+    pub async fn get<B>(&self, k: &B) -> Option<CacheReadGuard<'_, V>>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        let found = self.lookup(k).await;
+        self.counters.lookup(found.is_some());
+        found
+    }
+
+    /// Retrieve references to many values inside the cache.
+    ///
+    /// All keys are resolved under a single lock, rather than acquiring the lock once
+    /// per key as with repeated calls to `Cache::get`. The returned references are in
+    /// the same order as the provided keys, and are otherwise identical to `Cache::get`.
+    pub async fn get_many<'a, B, I>(&self, keys: I) -> Vec<Option<CacheReadGuard<'_, V>>>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized + 'a,
+        I: IntoIterator<Item = &'a B>,
+    {
+        let found: Vec<_> = if self.sliding {
+            let mut guard = self.store.write().await;
+            keys.into_iter()
+                .map(|k| {
+                    let (key, found) = Self::find_mut(&mut guard, k)?;
+                    let valid = unpack!(found)?;
+
+                    self.slide(key, valid);
+                    valid.touch(self.access());
+
+                    Some(CacheReadGuard {
+                        entry: valid as *const CacheEntry<V>,
+                        marker: PhantomData,
+                    })
+                })
+                .collect()
+        } else {
+            let guard = self.store.read().await;
+            keys.into_iter()
+                .map(|k| {
+                    let valid = guard.get(k).and_then(|entry| unpack!(entry))?;
+
+                    valid.touch(self.access());
+
+                    Some(CacheReadGuard {
+                        entry: valid as *const CacheEntry<V>,
+                        marker: PhantomData,
+                    })
+                })
+                .collect()
+        };
+
+        for guard in &found {
+            self.counters.lookup(guard.is_some());
+        }
+
+        found
+    }
+
+    /// Retrieve clones of many values inside the cache.
+    ///
+    /// This is equivalent to `Cache::get_many`, so all keys are resolved under a
+    /// single lock and the values are returned in the same order as the keys. As
+    /// the values are cloned, they can be held without borrowing the cache.
+    pub async fn get_many_cloned<'a, B, I>(&self, keys: I) -> Vec<Option<V>>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized + 'a,
+        I: IntoIterator<Item = &'a B>,
+        V: Clone,
+    {
+        self.get_many(keys)
+            .await
+            .into_iter()
+            .map(|guard| guard.map(|guard| guard.value().clone()))
+            .collect()
+    }
+
+    /// Retrieve a reference to a value inside the cache, without tracking stats.
+    async fn lookup<B>(&self, k: &B) -> Option<CacheReadGuard<'_, V>>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        if self.sliding {
+            let mut guard = self.store.write().await;
+            let (key, found) = Self::find_mut(&mut guard, k)?;
+            self.record(key);
+
+            // track expired entries as they're discovered, for estimates
+            if found.is_expired() && found.mark_stale() {
+                self.stale.fetch_add(1, Ordering::Relaxed);
+            }
+
+            let valid = unpack!(found)?;
+
+            self.slide(key, valid);
+            valid.touch(self.access());
+
+            return Some(CacheReadGuard {
+                entry: valid,
+                marker: PhantomData,
+            });
+        }
+
+        let guard = self.store.read().await;
+        let (key, found) = guard.get_key_value(k)?;
+        self.record(key);
+
+        // track expired entries as they're discovered, for estimates
+        if found.is_expired() && found.mark_stale() {
+            self.stale.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let valid = unpack!(found)?;
+
+        valid.touch(self.access());
+
+        Some(CacheReadGuard {
+            entry: valid,
+            marker: PhantomData,
+        })
+    }
+
+    /// Retrieve a value inside the cache, mapped through a closure.
+    ///
+    /// This is equivalent to calling `Cache::get` and applying the closure to the
+    /// guarded value, except that the guard is dropped before the result is returned.
+    /// This is convenient to convert a value into an owned view (e.g. deserializing)
+    /// without having to keep track of a `CacheReadGuard`.
+    pub async fn get_map<B, T, F>(&self, k: &B, f: F) -> Option<T>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+        F: FnOnce(&V) -> T,
+    {
+        self.get(k).await.map(|found| f(found.value()))
+    }
+
+    /// Retrieve a mutable reference to a value inside the cache.
+    ///
+    /// The returned reference is bound inside a `CacheWriteGuard`, which will hold
+    /// the write lock of the cache until it is dropped. This differs to `Cache::get`,
+    /// so the guard should be dropped as soon as possible.
+    pub async fn get_mut<B>(&self, k: &B) -> Option<CacheWriteGuard<'_, K, V>>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        let mut guard = self.store.write().await;
+        let (key, found) = Self::find_mut(&mut guard, k)?;
+        let valid = unpack!(found)?;
+
+        self.slide(key, valid);
+        valid.touch(self.access());
+
+        Some(CacheWriteGuard {
+            entry: valid,
+            _guard: guard,
+        })
+    }
+
+    /// Retrieve clones of all unexpired entries with keys inside the provided range.
+    ///
+    /// This maps directly onto `BTreeMap::range`, so only the entries inside the
+    /// range are visited. Bounds can be provided using any type the keys can be
+    /// borrowed as, e.g. `get_range::<str, _>((Bound::Included("a"), Bound::Excluded("c")))`
+    /// for keys of type `String` (the borrowed type can't always be inferred).
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `BTreeMap::range`, i.e. if the start of the range
+    /// is greater than the end, or if the start and end are equal and excluded.
+    pub async fn get_range<B, R>(&self, range: R) -> Vec<(K, V)>
+    where
+        R: RangeBounds<B>,
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+        V: Clone,
+    {
+        self.store
+            .read()
+            .await
+            .range::<B, R>(range)
+            .filter(|(_, entry)| !entry.is_expired())
+            .map(|(key, entry)| (key.clone(), entry.value().clone()))
+            .collect()
+    }
+
+    /// Retrieve a reference to a value inside the cache, computing it on a miss.
+    ///
+    /// The key is checked under an upgradable read lock, and only if the key is
+    /// absent (or expired) will the lock be upgraded and the provided closure be
+    /// run to compute a value to insert. As the closure is run under the write
+    /// lock, it will only ever be run once for any given miss; concurrent callers
+    /// will wait for the value to be inserted rather than computing their own.
+    ///
+    /// Values which can be computed synchronously can be provided by returning
+    /// `std::future::ready(value)` from the closure.
+    pub async fn get_or_insert_with<E, F, Fut>(&self, k: K, e: E, f: F) -> CacheLoadGuard<'_, V>
+    where
+        E: Into<CacheExpiration>,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let guard = self.store.upgradable_read().await;
+
+        if let Some(found) = guard.get(&k) {
+            if let Some(valid) = unpack!(found) {
+                if !self.sliding {
+                    self.record(&k);
+                    valid.touch(self.access());
+                    self.counters.lookup(true);
+
+                    return CacheLoadGuard::Cached(CacheReadGuard {
+                        entry: valid,
+                        marker: PhantomData,
+                    });
+                }
+            }
+        }
+
+        let mut guard = RwLockUpgradableReadGuard::upgrade(guard).await;
+
+        if let Some(found) = guard.get_mut(&k) {
+            if let Some(valid) = unpack!(found) {
+                self.record(&k);
+                self.slide(&k, valid);
+                valid.touch(self.access());
+                self.counters.lookup(true);
+
+                return CacheLoadGuard::Cached(CacheReadGuard {
+                    entry: valid,
+                    marker: PhantomData,
+                });
+            }
+        }
+
+        self.counters.lookup(false);
+
+        let entry = self.create(&k, f().await, e.into());
+        let len = guard.len();
+
+        self.inserting(&k, &entry);
+        self.recorded(&guard, &k);
+
+        let (admitted, victim) = self.admit(&guard, &k);
+
+        if !admitted {
+            self.announce_removal(&k, &entry, EvictionCause::Capacity);
+            drop(guard);
+            return self.reject(k, entry);
+        }
+
+        let mut evicted = self.reserve(&mut guard, &k, entry.weight(), victim);
+
+        self.reweigh(None, Some(&entry));
+        self.index(&k, &entry);
+        self.wake(&k);
+
+        let key = self.watch(&k);
+        let inserted: *const CacheEntry<V> = match guard.entry(k) {
+            Entry::Vacant(vacant) => vacant.insert(entry),
+            Entry::Occupied(mut occupied) => {
+                let displaced = occupied.insert(entry);
+                self.reweigh(Some(&displaced), None);
+                if self.observed() {
+                    evicted.push((occupied.key().clone(), displaced, EvictionCause::Replaced));
+                }
+                occupied.into_mut()
+            }
+        };
+
+        for (key, entry, cause) in &evicted {
+            self.announce_removal(key, entry, *cause);
+        }
+
+        self.emit(key, CacheEvent::Inserted);
+
+        let risen = self.risen(len, guard.len());
+
+        drop(guard);
+        self.rise(risen);
+
+        for (key, entry, cause) in &evicted {
+            self.notify(key, entry, *cause);
+        }
+
+        for (key, entry, cause) in evicted {
+            self.release(key, entry, cause);
+        }
+
+        CacheLoadGuard::Cached(CacheReadGuard {
+            entry: inserted,
+            marker: PhantomData,
+        })
+    }
+
+    /// Retrieve a reference to a value inside the cache, loading it on a miss.
+    ///
+    /// This is an infallible version of `Cache::get_or_try_insert_with`, so no lock
+    /// is held while the loader runs. Concurrent loads of the same key are coalesced,
+    /// so the loader runs at most once per key whilst a load is in flight; any other
+    /// callers missing the same key will wait for it and read the inserted value.
+    pub async fn get_or_load<E, F, Fut>(&self, k: K, e: E, loader: F) -> CacheLoadGuard<'_, V>
+    where
+        E: Into<CacheExpiration>,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let loaded = self
+            .get_or_try_insert_with(k, e, || async { Ok::<_, Infallible>(loader().await) })
+            .await;
+
+        match loaded {
+            Ok(guard) => guard,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Retrieve a reference to a value inside the cache, loading it on a miss.
+    ///
+    /// Unlike `Cache::get_or_insert_with`, the provided loader is fallible and no
+    /// lock is held while it runs; this avoids stalling the cache on slow loaders.
+    /// If the loader fails the error is returned and nothing is inserted. If
+    /// another caller inserted the key while the loader was running, the value
+    /// already present is preferred and the loaded value is discarded.
+    ///
+    /// Concurrent loads of the same key are coalesced, so only a single caller
+    /// will run a loader at any point; the others will wait for it to complete
+    /// and then read the inserted value. If the loader fails (or panics), one of
+    /// the waiting callers will run its own loader instead.
+    pub async fn get_or_try_insert_with<E, F, Fut, Err>(
+        &self,
+        k: K,
+        e: E,
+        f: F,
+    ) -> Result<CacheLoadGuard<'_, V>, Err>
+    where
+        E: Into<CacheExpiration>,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, Err>>,
+    {
+        if let Some(guard) = self.get(&k).await {
+            return Ok(CacheLoadGuard::Cached(guard));
+        }
+
+        // wait for any other callers loading the same key
+        let flight = Flight::new(&self.loading, k.clone());
+        let _lock = flight.lock().await;
+
+        // the key may have been loaded whilst we waited
+        if let Some(guard) = self.lookup(&k).await {
+            return Ok(CacheLoadGuard::Cached(guard));
+        }
+
+        let value = f().await?;
+        let entry = self.create(&k, value, e.into());
+        let mut guard = self.store.write().await;
+        let len = guard.len();
+
+        let mut evicted = match guard.get(&k) {
+            Some(found) if !found.is_expired() => Vec::new(),
+            _ => {
+                self.recorded(&guard, &k);
+
+                let (admitted, victim) = self.admit(&guard, &k);
+
+                if !admitted {
+                    self.inserting(&k, &entry);
+                    self.announce_removal(&k, &entry, EvictionCause::Capacity);
+                    drop(guard);
+                    return Ok(self.reject(k, entry));
+                }
+
+                self.reserve(&mut guard, &k, entry.weight(), victim)
+            }
+        };
+
+        let mut key = None;
+        let stored: *const CacheEntry<V> = match guard.entry(k) {
+            Entry::Vacant(vacant) => {
+                self.inserting(vacant.key(), &entry);
+                self.reweigh(None, Some(&entry));
+                self.index(vacant.key(), &entry);
+                self.wake(vacant.key());
+                key = self.watch(vacant.key());
+                vacant.insert(entry)
+            }
+            Entry::Occupied(mut occupied) => {
+                if occupied.get().is_expired() {
+                    self.inserting(occupied.key(), &entry);
+                    self.index(occupied.key(), &entry);
+                    self.wake(occupied.key());
+                    let displaced = occupied.insert(entry);
+                    self.reweigh(Some(&displaced), Some(occupied.get()));
+                    if self.observed() {
+                        evicted.push((occupied.key().clone(), displaced, EvictionCause::Replaced));
+                    }
+                    key = self.watch(occupied.key());
+                }
+                occupied.into_mut()
+            }
+        };
+
+        for (key, entry, cause) in &evicted {
+            self.announce_removal(key, entry, *cause);
+        }
+
+        self.emit(key, CacheEvent::Inserted);
+
+        let risen = self.risen(len, guard.len());
+
+        drop(guard);
+        self.rise(risen);
+
+        for (key, entry, cause) in &evicted {
+            self.notify(key, entry, *cause);
+        }
+
+        for (key, entry, cause) in evicted {
+            self.release(key, entry, cause);
+        }
+
+        Ok(CacheLoadGuard::Cached(CacheReadGuard {
+            entry: stored,
+            marker: PhantomData,
+        }))
+    }
+
+    /// Retrieve the keys of all unexpired entries inside the cache.
+    ///
+    /// Keys are returned in order, cloned under a single read lock. The returned
+    /// keys are a snapshot, so may be stale as soon as they have been returned.
+    ///
+    /// Note that this is calculated by walking the set of entries and
+    /// should therefore not be used in performance sensitive situations.
+    pub async fn keys(&self) -> Vec<K> {
+        self.store
+            .read()
+            .await
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired())
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Retrieve the keys of all unexpired entries which start with the provided prefix.
+    ///
+    /// Only the entries starting with the prefix are visited, as they're stored as a
+    /// contiguous range inside the cache. As with `Cache::keys`, the returned keys are
+    /// a snapshot.
+    pub async fn keys_with_prefix(&self, prefix: &str) -> Vec<K>
+    where
+        K: Borrow<str>,
+    {
+        Self::prefixed(&*self.store.read().await, prefix)
+            .filter(|(_, entry)| !entry.is_expired())
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Retrieve the keys of all entries inside the cache, including expired entries.
+    ///
+    /// This *does* include entries which may be expired but are not yet evicted, which
+    /// can be useful when tuning the monitor. As with `Cache::keys`, the returned keys
+    /// are a snapshot.
+    pub async fn keys_unfiltered(&self) -> Vec<K> {
+        self.store.read().await.keys().cloned().collect()
+    }
+
+    /// Retrieve a report of the most recently completed purge of the cache.
+    ///
+    /// This covers calls to `Cache::purge`, as well as the purges made by monitors.
+    /// The report contains the number of entries removed, the total time taken, and
+    /// the time spent holding the write lock, to allow exporting these as metrics.
+    /// If the cache has never been purged, this will return `None`.
+    pub fn last_purge(&self) -> Option<PurgeReport> {
+        *self.purged.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Retrieve the number of entries inside the cache.
+    ///
+    /// This *does* include entries which may be expired but are not yet evicted. In
+    /// future there may be an API addition to find the unexpired count, but as it's
+    /// relatively expensive it has been omitted for the time being.
+    pub async fn len(&self) -> usize {
+        self.store.read().await.len()
+    }
+
+    /// Insert a key/value pair into the cache with an associated expiration.
+    ///
+    /// The third argument controls expiration, which can be provided using any type which
+    /// implements `Into<CacheExpiration>`. This allows for various different syntax based
+    /// on your use case. If you do not want expiration, use `Cache::insert_untracked`.
+    pub async fn insert<E>(&self, k: K, v: V, e: E) -> Option<V>
+    where
+        E: Into<CacheExpiration>,
+    {
+        let entry = self.create(&k, v, e.into());
+        self.inserting(&k, &entry);
+
+        let store = self.store.write().await;
+
+        self.insert_locked(store, k, entry)
+            .map(CacheEntry::into_inner)
+    }
+
+    /// Insert a key/value pair into the cache, returning any displaced expiration.
+    ///
+    /// This behaves identically to `Cache::insert`, except that the expiration of the
+    /// displaced entry is returned alongside the value. This is useful to determine
+    /// how much time remained on an entry before it was overwritten. As with insert,
+    /// expired entries are never returned.
+    pub async fn insert_replace<E>(&self, k: K, v: V, e: E) -> Option<(V, CacheExpiration)>
+    where
+        E: Into<CacheExpiration>,
+    {
+        let entry = self.create(&k, v, e.into());
+        self.inserting(&k, &entry);
+
+        let store = self.store.write().await;
+
+        self.insert_locked(store, k, entry)
+            .map(CacheEntry::into_parts)
+    }
+
+    /// Insert a key/value pair into the cache without an associated expiration.
+    ///
+    /// This is equivalent to calling `Cache::insert` with `CacheExpiration::none()`,
+    /// and the entry will remain in the cache until it is manually removed.
+    pub async fn insert_untracked(&self, k: K, v: V) -> Option<V> {
+        self.insert(k, v, CacheExpiration::none()).await
+    }
+
+    /// Insert a key/value pair into the cache if the key is absent.
+    ///
+    /// If an unexpired entry already exists for the key, the provided value is
+    /// given back inside an `Err`. Expired entries are treated as absent and are
+    /// replaced. The check and insertion happen under a single write lock, so two
+    /// racing calls for the same key cannot both succeed.
+    pub async fn insert_if_absent<E>(&self, k: K, v: V, e: E) -> Result<(), V>
+    where
+        E: Into<CacheExpiration>,
+    {
+        let mut store = self.store.write().await;
+
+        if store.get(&k).and_then(|entry| unpack!(entry)).is_some() {
+            return Err(v);
+        }
+
+        let entry = self.create(&k, v, e.into());
+        self.inserting(&k, &entry);
+
+        let inserted = self.put(&mut store, k, entry);
+
+        drop(store);
+        self.settle(inserted);
+
+        Ok(())
+    }
+
+    /// Attempt to insert a key/value pair into the cache if the key is absent.
+    ///
+    /// This is an alias of `Cache::insert_if_absent`, so see the documentation
+    /// of that method for further details.
+    pub async fn try_insert<E>(&self, k: K, v: V, e: E) -> Result<(), V>
+    where
+        E: Into<CacheExpiration>,
+    {
+        self.insert_if_absent(k, v, e).await
+    }
+
+    /// Insert many key/value pairs into the cache with a shared expiration.
+    ///
+    /// This is equivalent to `Cache::insert_many`, except that the expiration is
+    /// converted once and then shared by every pair; this is the common case when
+    /// warming a cache. The returned value is the number of pairs inserted.
+    pub async fn insert_batch<E, I>(&self, items: I, e: E) -> usize
+    where
+        E: Into<CacheExpiration>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let e = e.into();
+        self.insert_many(items.into_iter().map(|(k, v)| (k, v, e)))
+            .await
+    }
+
+    /// Insert many key/value pairs into the cache with associated expirations.
+    ///
+    /// All pairs are inserted under a single write lock, which is much cheaper than
+    /// calling `Cache::insert` repeatedly when populating a cache. Listeners are only
+    /// notified of any removed entries once all pairs have been inserted.
+    ///
+    /// The returned value is the number of pairs inserted.
+    pub async fn insert_many<E, I>(&self, items: I) -> usize
+    where
+        E: Into<CacheExpiration>,
+        I: IntoIterator<Item = (K, V, E)>,
+    {
+        // weigh all entries before locking, as weighers and hooks may be expensive
+        let entries: Vec<_> = items
+            .into_iter()
+            .map(|(k, v, e)| {
+                let entry = self.create(&k, v, e.into());
+                self.inserting(&k, &entry);
+                (k, entry)
+            })
+            .collect();
+
+        let mut store = self.store.write().await;
+
+        let inserted: Vec<_> = entries
+            .into_iter()
+            .map(|(k, entry)| self.put(&mut store, k, entry))
+            .collect();
+
+        drop(store);
+
+        let count = inserted.len();
+
+        for inserted in inserted {
+            self.settle(inserted);
+        }
+
+        count
+    }
+
+    /// Extend the cache with a batch of key/value pairs and associated expirations.
+    ///
+    /// This is the asynchronous counterpart to building a cache via `FromIterator`,
+    /// and appends every pair to an existing cache under a single write lock.
+    pub async fn extend<E, I>(&self, items: I)
+    where
+        E: Into<CacheExpiration>,
+        I: IntoIterator<Item = (K, V, E)>,
+    {
+        self.insert_many(items).await;
+    }
+
+    /// Insert a key/value pair into the cache with an associated expiration and tags.
+    ///
+    /// This behaves identically to `Cache::insert`, except that the provided tags are
+    /// attached to the entry so that it can later be removed alongside all other entries
+    /// sharing a tag via `Cache::invalidate_tag`. Tags are carried across by
+    /// `Cache::replace`, but any other insertion for the key will discard them.
+    pub async fn insert_tagged<E>(&self, k: K, v: V, e: E, tags: Vec<String>) -> Option<V>
+    where
+        E: Into<CacheExpiration>,
+    {
+        let mut entry = self.create(&k, v, e.into());
+        entry.set_tags(tags);
+        self.inserting(&k, &entry);
+
+        let store = self.store.write().await;
+
+        self.insert_locked(store, k, entry)
+            .map(CacheEntry::into_inner)
+    }
+
+    /// Remove all entries from the cache which carry the provided tag.
+    ///
+    /// Note that this is calculated by walking the set of entries, as tags are stored
+    /// alongside each entry; this keeps tags consistent with the cache regardless of
+    /// how entries are removed. Listeners are notified of all removed entries.
+    ///
+    /// The returned value is the number of entries removed.
+    pub async fn invalidate_tag(&self, tag: &str) -> usize {
+        let removed = self
+            .remove_where(EvictionCause::Manual, |_, entry| {
+                entry.tags().iter().any(|t| t == tag)
+            })
+            .await;
+
+        let count = removed.len();
+        self.discard(removed, EvictionCause::Manual);
+        count
+    }
+
+    /// Wait for an unexpired entry to exist for a key in the cache.
+    ///
+    /// If no unexpired entry exists, the caller waits until the key is inserted by
+    /// `Cache::insert` (or any of the insert methods), rather than polling the cache
+    /// repeatedly. This makes the cache usable as a rendezvous point between tasks.
+    /// If no entry is available before `timeout` elapses, `None` is returned.
+    ///
+    /// # Panics
+    ///
+    /// If the `tokio` feature is enabled (by any crate in the dependency graph), this
+    /// panics when polled outside of a `tokio` runtime, as `tokio` timers are used.
+    pub async fn wait_for<B>(&self, k: &B, timeout: Duration) -> Option<CacheReadGuard<'_, V>>
+    where
+        K: Borrow<B>,
+        B: Ord + ToOwned<Owned = K> + ?Sized,
+    {
+        let mut timer = Box::pin(timer::sleep(timeout));
+
+        loop {
+            // register before checking, so that no insertion can be missed
+            let waiting = Waiting::new(&self.waiting, k.to_owned());
+
+            if let Some(found) = self.get(k).await {
+                return Some(found);
+            }
+
+            // wait for either an insertion of the key, or the timeout
+            let woken = future::poll_fn(|cx| {
+                if waiting.waiter.poll(cx).is_ready() {
+                    return Poll::Ready(true);
+                }
+                timer.as_mut().poll(cx).map(|_| false)
+            });
+
+            if !woken.await {
+                return None;
+            }
+        }
+    }
+
+    /// Check whether the cache is empty.
+    pub async fn is_empty(&self) -> bool {
+        self.store.read().await.is_empty()
+    }
+
+    /// Merge the unexpired entries of another cache into this cache.
+    ///
+    /// The other cache is consumed, and all of its entries are inserted under a
+    /// single write lock, preserving their expirations. If an unexpired entry for
+    /// a key already exists, `resolve` is called with the existing and incoming
+    /// values to determine the merged value; `|_, incoming| incoming` will keep the
+    /// incoming value, whereas `|existing, _| existing` will keep the existing one.
+    ///
+    /// Expirations are carried across exactly, so expiry jitter is not re-applied.
+    /// As conflicting values are passed to `resolve`, listeners are only notified
+    /// of expired entries replaced by the merge, and of entries evicted to make room
+    /// for the merged entries. The returned value is the number of entries merged.
+    pub async fn merge<F>(&self, other: Cache<K, V>, mut resolve: F) -> usize
+    where
+        F: FnMut(V, V) -> V,
+    {
+        let mut store = self.store.write().await;
+        let mut expired = Vec::new();
+
+        let inserted: Vec<_> = other
+            .store
+            .into_inner()
+            .into_iter()
+            .filter(|(_, entry)| !entry.is_expired())
+            .map(|(k, incoming)| {
+                let tags = incoming.tags().to_vec();
+                let (mut value, expiration) = incoming.into_parts();
+
+                // resolve any conflict with an existing unexpired entry
+                if let Some((key, existing)) = store.remove_entry(&k) {
+                    self.unindex(&key, &existing);
+                    self.reweigh(Some(&existing), None);
+
+                    if existing.is_expired() {
+                        expired.push((key, existing));
+                    } else {
+                        if let Some(hook) = &self.on_remove {
+                            hook(&key, existing.value());
+                        }
+                        value = resolve(existing.into_inner(), value);
+                    }
+                }
+
+                let mut entry = self.build(&k, value, expiration);
+                entry.set_tags(tags);
+                self.inserting(&k, &entry);
+
+                self.put(&mut store, k, entry)
+            })
+            .collect();
+
+        for (key, entry) in &expired {
+            self.announce_removal(key, entry, EvictionCause::Expired);
+        }
+
+        drop(store);
+
+        // expired entries are removed in the same way as `Cache::remove`
+        for (key, entry) in expired {
+            self.notify(&key, &entry, EvictionCause::Expired);
+            self.release(key, entry, EvictionCause::Expired);
+        }
+
+        let count = inserted.len();
+
+        for inserted in inserted {
+            self.settle(inserted);
+        }
+
+        count
+    }
+
+    /// Retrieve a `Future` used to monitor expired keys.
+    ///
+    /// This future must be spawned on whatever runtime you are using inside your
+    /// application; not doing this will result in keys never being expired.
+    ///
+    /// For expiration logic, please see `Cache::purge`, as this is used under the hood.
+    ///
+    /// # Panics
+    ///
+    /// If the `tokio` feature is enabled (by any crate in the dependency graph), this
+    /// panics when polled outside of a `tokio` runtime, as `tokio` timers are used.
+    pub async fn monitor(&self, sample: usize, threshold: f64, frequency: Duration) {
+        self.monitor_with_shutdown(sample, threshold, frequency, future::pending::<()>())
+            .await
+    }
+
+    /// Retrieve a `Future` used to monitor expired keys, until a shutdown signal.
+    ///
+    /// This behaves identically to `Cache::monitor`, except that the returned future
+    /// will complete once the provided `shutdown` future completes. Any purge which
+    /// is already in progress when the shutdown future completes will be allowed to
+    /// finish, so the cache is never left mid-purge.
+    ///
+    /// # Panics
+    ///
+    /// If the `tokio` feature is enabled (by any crate in the dependency graph), this
+    /// panics when polled outside of a `tokio` runtime, as `tokio` timers are used.
+    pub async fn monitor_with_shutdown<S>(
+        &self,
+        sample: usize,
+        threshold: f64,
+        frequency: Duration,
+        shutdown: S,
+    ) where
+        S: Future,
+    {
+        let mut interval = Interval::new(frequency);
+        let mut shutdown = Box::pin(shutdown);
+
+        loop {
+            // wait for either the next tick, or the shutdown signal
+            let tick = future::poll_fn(|cx| {
+                if shutdown.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(false);
+                }
+                interval.poll_tick(cx).map(|_| true)
+            });
+
+            if !tick.await {
+                break;
+            }
+
+            self.purge(sample, threshold).await;
+        }
+
+        debug!("{}monitor shutdown", self.label);
+    }
+
+    /// Retrieve a `Future` used to monitor expired keys at an adaptive frequency.
+    ///
+    /// This behaves like `Cache::monitor`, except that the delay between purges is
+    /// adjusted based on the ratio of sampled entries removed by the previous purge.
+    /// If at least `threshold` were removed the delay is halved, otherwise it is
+    /// doubled, always staying within the bounds of `min` and `max`. This keeps a
+    /// busy cache clean without repeatedly waking up a quiet cache.
+    ///
+    /// # Panics
+    ///
+    /// If the `tokio` feature is enabled (by any crate in the dependency graph), this
+    /// panics when polled outside of a `tokio` runtime, as `tokio` timers are used.
+    pub async fn monitor_adaptive(
+        &self,
+        sample: usize,
+        threshold: f64,
+        min: Duration,
+        max: Duration,
+    ) {
+        let mut frequency = min;
+
+        loop {
+            timer::sleep(frequency).await;
+
+            let (removed, sampled) = self.sweep(sample, threshold).await;
+
+            // shrink the delay when busy, and grow it when quiet
+            frequency = if sampled > 0 && removed as f64 >= sampled as f64 * threshold {
+                cmp::max(frequency / 2, min)
+            } else {
+                cmp::min(frequency * 2, max)
+            };
+
+            trace!("{}next adaptive purge in {:.0?}", self.label, frequency);
+        }
+    }
+
+    /// Retrieve clones of all unexpired keys and values inside the cache.
+    ///
+    /// This is equivalent to `Cache::snapshot`; the lock is released before the
+    /// pairs are returned, so callers can iterate them without blocking the cache.
+    pub async fn pairs_cloned(&self) -> Vec<(K, V)>
+    where
+        V: Clone,
+    {
+        self.snapshot().await
+    }
+
+    /// Retrieve a reference to a value inside the cache, without accessing it.
+    ///
+    /// Unlike `Cache::get`, this is read-only with respect to eviction metadata;
+    /// the entry is not marked as recently used, sliding expirations are not
+    /// refreshed, and the lookup is not tracked in the statistics. This is useful
+    /// for inspecting a cache without distorting eviction decisions.
+    pub async fn peek<B>(&self, k: &B) -> Option<CacheReadGuard<'_, V>>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        let guard = self.store.read().await;
+        let found = guard.get(k)?;
+        let valid = unpack!(found)?;
+
+        Some(CacheReadGuard {
+            entry: valid,
+            marker: PhantomData,
+        })
+    }
+
+    /// Remove the expiration of an entry in the cache.
+    ///
+    /// This mirrors the `PERSIST` command in Redis, and returns whether an unexpired
+    /// entry was found for the key. Calling this on an entry which has no expiration
+    /// is a no-op, which will still return `true`.
+    pub async fn persist<B>(&self, k: &B) -> bool
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        let mut guard = self.store.write().await;
+
+        match guard.get_mut(k).and_then(|entry| unpack!(entry)) {
+            Some(entry) => {
+                entry.set_expiration(CacheExpiration::none());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove and return the unexpired entry with the smallest key in the cache.
+    ///
+    /// Any expired entries found before the first unexpired entry are removed in
+    /// passing, and listeners are notified of them as expired entries.
+    pub async fn pop_first(&self) -> Option<(K, V)> {
+        self.pop(BTreeMap::pop_first).await
+    }
+
+    /// Remove and return the unexpired entry with the largest key in the cache.
+    ///
+    /// Any expired entries found before the last unexpired entry are removed in
+    /// passing, and listeners are notified of them as expired entries.
+    pub async fn pop_last(&self) -> Option<(K, V)> {
+        self.pop(BTreeMap::pop_last).await
+    }
+
+    /// Cleanses the cache of expired entries.
+    ///
+    /// Keys are expired using the same logic as the popular caching system Redis:
+    ///
+    /// 1. Wait until the next tick of `frequency`.
+    /// 2. Take a sample of `sample` keys from the cache.
+    /// 3. Remove any expired keys from the sample.
+    /// 4. Based on `threshold` percentage:
+    ///    4a. If more than `threshold` were expired, goto #2.
+    ///    4b. If less than `threshold` were expired, goto #1.
+    ///
+    /// This means that at any point you may have up to `threshold` percent of your
+    /// cache storing expired entries (assuming the monitor just ran), so make sure
+    /// to tune your frequency, sample size, and threshold accordingly.
+    ///
+    /// Sampled entries are always distinct, so if `sample` is at least the number
+    /// of entries inside the cache then every entry will be checked in a batch. For
+    /// a precise pass over every entry, see `Cache::clear_expired`.
+    pub async fn purge(&self, sample: usize, threshold: f64) {
+        self.sweep(sample, threshold).await;
+    }
+
+    /// Cleanses the cache of expired entries, as per `Cache::purge`.
+    ///
+    /// The returned values are the total number of entries removed and sampled
+    /// across all batches, which is used to adapt the frequency of a monitor.
+    async fn sweep(&self, sample: usize, threshold: f64) -> (usize, usize) {
+        if let Some(deadlines) = &self.deadlines {
+            return self.sweep_precise(deadlines, sample).await;
+        }
+
+        let start = Instant::now();
+
+        let mut locked = Duration::from_nanos(0);
+        let mut removed = 0;
+        let mut sampled = 0;
+
+        loop {
+            // lock the store and grab a generator
+            let store = self.store.upgradable_read().await;
+
+            // once we're empty, no point carrying on
+            if store.is_empty() {
+                break;
+            }
+
+            // determine the sample size of the batch
+            let sample = cmp::min(sample, store.len());
+
+            // select the keys to remove via the eviction policy
+            let keys = self.policy.select(StoreView::new(&store), sample);
+
+            let evicted: Vec<_> = {
+                // upgrade to a write guard so that we can make our changes
+                let acquired = Instant::now();
+                let mut store = RwLockUpgradableReadGuard::upgrade(store).await;
+
+                // remove all selected keys
+                let evicted: Vec<_> = keys
+                    .iter()
+                    .filter_map(|key| store.remove_entry(key))
+                    .collect();
+
+                // release the weight of all removed entries
+                self.reweigh(evicted.iter().map(|(_, entry)| entry), None);
+
+                for (key, entry) in &evicted {
+                    self.announce_removal(key, entry, EvictionCause::Capacity);
+                }
+
+                // increment the lock timer tracking directly
+                locked = locked.checked_add(acquired.elapsed()).unwrap();
+
+                evicted
+            };
+
+            // notify any listener now that the lock has been released
+            for (key, entry) in &evicted {
+                let cause = if entry.is_expired() {
+                    EvictionCause::Expired
+                } else {
+                    EvictionCause::Capacity
+                };
+                self.notify(key, entry, cause);
+            }
+
+            // counter to track removed keys
+            let gone = evicted.len();
+
+            // release the removed entries now that listeners have been notified
+            for (key, entry) in evicted {
+                self.release(key, entry, EvictionCause::Capacity);
+            }
+
+            // log out now many of the sampled keys were removed
+            if log_enabled!(Level::Trace) {
+                trace!(
+                    "{}removed {} / {} ({:.2}%) of the sampled keys",
+                    self.label,
+                    gone,
+                    sample,
+                    (gone as f64 / sample as f64) * 100f64,
+                );
+            }
+
+            // bump total remove and sample counts
+            removed += gone;
+            sampled += sample;
+            self.counters.evicted(gone as u64);
+
+            // break the loop if we don't meet thresholds
+            if (gone as f64) < (sample as f64 * threshold) {
+                break;
+            }
+        }
+
+        // log out the completion as well as the time taken in millis
+        if log_enabled!(Level::Debug) {
+            debug!(
+                "{}purge loop removed {} entries in {:.0?} ({:.0?} locked)",
+                self.label,
+                removed,
+                start.elapsed(),
+                locked
+            );
+        }
+
+        self.report(removed, sampled, start, locked);
+
+        (removed, sampled)
+    }
+
+    /// Cleanses the cache of expired entries, using the precise expiration index.
+    ///
+    /// Entries are removed in order of deadline, in batches of `batch` entries per
+    /// lock. The returned values are the same as those of `Cache::sweep`.
+    async fn sweep_precise(
+        &self,
+        deadlines: &SyncMutex<BTreeSet<(Instant, K)>>,
+        batch: usize,
+    ) -> (usize, usize) {
+        let start = Instant::now();
+        let batch = cmp::max(batch, 1);
+
+        let mut locked = Duration::from_nanos(0);
+        let mut removed = 0;
+        let mut sampled = 0;
+
+        loop {
+            let mut store = self.store.write().await;
+            let acquired = Instant::now();
+            let now = timer::now();
+
+            // pop the batch of deadlines which have passed
+            let mut index = Self::deadlines(deadlines);
+            let mut due = Vec::with_capacity(batch);
+
+            while due.len() < batch {
+                let first = match index.iter().next() {
+                    Some(first) if first.0 <= now => first.clone(),
+                    _ => break,
+                };
+                index.remove(&first);
+                due.push(first.1);
+            }
+
+            let popped = due.len();
+            let mut evicted = Vec::new();
+
+            for key in due {
+                let entry = match store.get(&key) {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+
+                // re-index any entries with a deadline which has moved
+                if !entry.is_expired() {
+                    if let Some(deadline) = entry.deadline() {
+                        index.insert((deadline, key));
+                    }
+                    continue;
+                }
+
+                evicted.extend(store.remove_entry(&key));
+            }
+
+            drop(index);
+
+            // release the weight of all removed entries
+            self.reweigh(evicted.iter().map(|(_, entry)| entry), None);
+
+            for (key, entry) in &evicted {
+                self.announce_removal(key, entry, EvictionCause::Expired);
+            }
+
+            drop(store);
+
+            // the lock was acquired at the start of the batch
+            locked = locked.checked_add(acquired.elapsed()).unwrap();
+
+            // notify any listener now that the lock has been released
+            for (key, entry) in &evicted {
+                self.notify(key, entry, EvictionCause::Expired);
+            }
+
+            removed += evicted.len();
+            sampled += popped;
+            self.counters.evicted(evicted.len() as u64);
+
+            // release the expired entries now that listeners have been notified
+            for (key, entry) in evicted {
+                self.release(key, entry, EvictionCause::Expired);
+            }
+
+            // stop once all passed deadlines have been handled
+            if popped < batch {
+                break;
+            }
+        }
+
+        if log_enabled!(Level::Debug) {
+            debug!(
+                "{}precise purge loop removed {} entries in {:.0?} ({:.0?} locked)",
+                self.label,
+                removed,
+                start.elapsed(),
+                locked
+            );
+        }
+
+        self.report(removed, sampled, start, locked);
+
+        (removed, sampled)
+    }
+
+    /// Store the report of a completed purge, to be retrieved via `Cache::last_purge`.
+    fn report(&self, removed: usize, sampled: usize, start: Instant, locked: Duration) {
+        let report = PurgeReport {
+            removed,
+            sampled,
+            elapsed: start.elapsed(),
+            locked,
+        };
+
+        *self.purged.lock().unwrap_or_else(|err| err.into_inner()) = Some(report);
+    }
+
+    /// Remove an entry from the cache and return any stored value.
+    pub async fn remove<B>(&self, k: &B) -> Option<V>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        let (key, entry) = self.take(k).await?;
+
+        if entry.is_expired() {
+            self.release(key, entry, EvictionCause::Expired);
+            return None;
+        }
+
+        Some(entry.into_inner())
+    }
+
+    /// Remove an entry from the cache and return the stored key, value and expiration.
+    ///
+    /// This allows an entry to be re-inserted with the exact same expiration it
+    /// had previously. As with `Cache::remove`, expired entries return `None`.
+    pub async fn remove_entry<B>(&self, k: &B) -> Option<(K, V, CacheExpiration)>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        let (key, entry) = self.take(k).await?;
+
+        if entry.is_expired() {
+            self.release(key, entry, EvictionCause::Expired);
+            return None;
+        }
+
+        let (value, expiration) = entry.into_parts();
+        Some((key, value, expiration))
+    }
+
+    /// Sets the expiration of an entry in the cache.
+    ///
+    /// The previous expiration is returned if an unexpired entry was found. Expired
+    /// entries are left untouched (returning `None`), to be removed by the purge loop.
+    pub async fn set_expiration<B, E>(&self, k: &B, e: E) -> Option<CacheExpiration>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+        E: Into<CacheExpiration>,
+    {
+        let mut guard = self.store.write().await;
+        let (key, found) = Self::find_mut(&mut guard, k)?;
+        let valid = unpack!(found)?;
+
+        let previous = valid.set_expiration(e.into());
+        self.index(key, valid);
+        Some(previous)
+    }
+
+    /// Remove many entries from the cache and return any stored values.
+    ///
+    /// All keys are removed under a single write lock, rather than acquiring the lock
+    /// once per key as with repeated calls to `Cache::remove`. The returned values are
+    /// in the same order as the provided keys, with `None` for any missing or expired
+    /// entries (so the number of removed entries can be counted via `Option::is_some`).
+    pub async fn remove_many<'a, B, I>(&self, keys: I) -> Vec<Option<V>>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized + 'a,
+        I: IntoIterator<Item = &'a B>,
+    {
+        let mut store = self.store.write().await;
+
+        let removed: Vec<_> = keys.into_iter().map(|k| store.remove_entry(k)).collect();
+
+        for (key, entry) in removed.iter().flatten() {
+            self.unindex(key, entry);
+        }
+
+        self.reweigh(removed.iter().flatten().map(|(_, entry)| entry), None);
+
+        for (key, entry) in removed.iter().flatten() {
+            self.announce_removal(key, entry, EvictionCause::Manual);
+        }
+
+        drop(store);
+
+        removed
+            .into_iter()
+            .map(|removed| {
+                let (key, entry) = removed?;
+
+                self.notify(&key, &entry, EvictionCause::Manual);
+
+                if entry.is_expired() {
+                    self.release(key, entry, EvictionCause::Expired);
+                    return None;
+                }
+
+                Some(entry.into_inner())
+            })
+            .collect()
+    }
+
+    /// Remove an entry from the cache if the stored value matches a predicate.
+    ///
+    /// The predicate is checked against the value under the write lock, so the entry
+    /// can't change between the check and the removal. The outer `Option` represents
+    /// whether an unexpired entry exists for the key, while the inner `Option` holds
+    /// the removed value, or `None` if the predicate returned `false`.
+    pub async fn remove_if<B, F>(&self, k: &B, f: F) -> Option<Option<V>>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+        F: FnOnce(&V) -> bool,
+    {
+        let mut store = self.store.write().await;
+        let found = store.get(k)?;
+        let valid = unpack!(found)?;
+
+        if !f(valid.value()) {
+            return Some(None);
+        }
+
+        let (key, entry) = store.remove_entry(k)?;
+        self.unindex(&key, &entry);
+        self.reweigh(Some(&entry), None);
+        self.announce_removal(&key, &entry, EvictionCause::Manual);
+
+        drop(store);
+        self.notify(&key, &entry, EvictionCause::Manual);
+
+        Some(Some(entry.into_inner()))
+    }
+
+    /// Remove all entries with keys inside the provided range from the cache.
+    ///
+    /// This maps directly onto `BTreeMap::range`, so only the entries inside the
+    /// range are visited, and bounds can be provided in the same way as with
+    /// `Cache::get_range`. Expired entries inside the range are also removed, but
+    /// are not included in the returned count of removed entries.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `Cache::get_range`.
+    pub async fn remove_range<B, R>(&self, range: R) -> usize
+    where
+        R: RangeBounds<B>,
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        let removed = self
+            .remove_selected(EvictionCause::Manual, |store| {
+                store
+                    .range::<B, R>(range)
+                    .map(|(key, _)| key.clone())
+                    .collect()
+            })
+            .await;
+
+        let count = removed
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired())
+            .count();
+        self.discard(removed, EvictionCause::Manual);
+        count
+    }
+
+    /// Remove all entries with keys starting with the provided prefix from the cache.
+    ///
+    /// Only the entries starting with the prefix are visited, as they're stored as a
+    /// contiguous range inside the cache. As with `Cache::remove_range`, any expired
+    /// entries are also removed but are not included in the returned count.
+    pub async fn remove_prefix(&self, prefix: &str) -> usize
+    where
+        K: Borrow<str>,
+    {
+        let removed = self
+            .remove_selected(EvictionCause::Manual, |store| {
+                Self::prefixed(store, prefix)
+                    .map(|(key, _)| key.clone())
+                    .collect()
+            })
+            .await;
+
+        let count = removed
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired())
+            .count();
+        self.discard(removed, EvictionCause::Manual);
+        count
+    }
+
+    /// Replace the value of an entry in the cache, only if the entry exists.
+    ///
+    /// This is the inverse of `Cache::insert_if_absent`; if an unexpired entry exists
+    /// its value and expiration are replaced and the previous value is returned. If
+    /// the key is missing (or expired) then `None` is returned and nothing is stored.
+    pub async fn replace<B, E>(&self, k: &B, v: V, e: E) -> Option<V>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+        E: Into<CacheExpiration>,
+    {
+        let mut store = self.store.write().await;
+
+        let (indexed, found) = Self::find_mut(&mut store, k)?;
+        let valid = unpack!(found)?;
+
+        let mut entry = self.create(indexed, v, e.into());
+        self.reweigh(Some(&*valid), Some(&entry));
+
+        // tags are carried across to the replacement entry
+        entry.set_tags(valid.set_tags(Vec::new()));
+
+        // only clone the key when there's a listener to notify
+        let key = if self.observed() {
+            Some(indexed.clone())
+        } else {
+            None
+        };
+        let displaced = mem::replace(valid, entry);
+        self.index(indexed, valid);
+        self.announce(indexed, CacheEvent::Updated);
+
+        drop(store);
+
+        if let Some(key) = &key {
+            self.notify(key, &displaced, EvictionCause::Replaced);
+        }
+
+        Some(displaced.into_inner())
+    }
+
+    /// Retain only the entries inside the cache which match a predicate.
+    ///
+    /// This mirrors `BTreeMap::retain`, removing all entries for which the predicate
+    /// returns `false` under a single write lock. Expired entries are always removed
+    /// as a side effect, without being passed to the predicate. Listeners are notified
+    /// of all removed entries after the lock has been released.
+    ///
+    /// The returned value is the number of entries removed.
+    pub async fn retain<F>(&self, mut f: F) -> usize
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let removed = self
+            .remove_where(EvictionCause::Manual, |key, entry| {
+                entry.is_expired() || !f(key, entry.value())
+            })
+            .await;
+
+        let count = removed.len();
+        self.discard(removed, EvictionCause::Manual);
+        count
+    }
+
+    /// Reset all statistics tracked by the cache back to zero.
+    pub fn reset_stats(&self) {
+        self.counters.reset()
+    }
+
+    /// Retrieve the number of keys currently being loaded into the cache.
+    ///
+    /// This counts the distinct keys with callers inside `Cache::get_or_insert_with`
+    /// (and friends) waiting on a load, and drops back to `0` once every caller has
+    /// completed (or been cancelled).
+    pub fn loading(&self) -> usize {
+        self.loading
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .len()
+    }
+
+    /// Retrieve a snapshot of the statistics tracked by the cache.
+    ///
+    /// Hits and misses are tracked on lookups made via `Cache::get` and the
+    /// `Cache::get_or_*` methods, with expired entries counting as a miss.
+    /// Evictions are tracked for entries removed by `Cache::purge`, as well as
+    /// for entries removed to make room when the cache is at capacity.
+    pub fn stats(&self) -> CacheStats {
+        self.counters.snapshot()
+    }
+
+    /// Retrieve a snapshot of all unexpired entries inside the cache.
+    ///
+    /// This clones every unexpired key and value under a single read lock, so
+    /// should not be used in performance sensitive situations. Entries which have
+    /// expired but have not yet been evicted are excluded, matching `Cache::get`.
+    pub async fn snapshot(&self) -> Vec<(K, V)>
+    where
+        V: Clone,
+    {
+        self.store
+            .read()
+            .await
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired())
+            .map(|(key, entry)| (key.clone(), entry.value().clone()))
+            .collect()
+    }
+
+    /// Re-arm the expiration of an entry in the cache to its original duration.
+    ///
+    /// This mirrors re-applying the same `EXPIRE` in Redis; the entry will expire
+    /// after the duration it was inserted with, starting from now. This returns
+    /// `false` if no unexpired entry exists, or if the entry was inserted with an
+    /// `Instant` (or without an expiration), as there is no duration to re-apply.
+    pub async fn touch<B>(&self, k: &B) -> bool
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        let mut guard = self.store.write().await;
+
+        let (key, valid) = match Self::find_mut(&mut guard, k) {
+            Some((key, entry)) if !entry.is_expired() => (key, entry),
+            _ => return false,
+        };
+
+        match *valid.expiration().duration() {
+            Some(duration) => {
+                Self::rearm(valid, duration);
+                self.index(key, valid);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Attempt to retrieve a reference to a value inside the cache without waiting.
+    ///
+    /// This behaves identically to `Cache::get`, except that `None` is returned
+    /// immediately if the cache is currently locked rather than waiting for the
+    /// lock to be released. Contended lookups are not tracked in the statistics.
+    ///
+    /// This allows a best-effort lookup from synchronous code (e.g. inside `Drop`).
+    /// Note that a `None` does *not* mean the key is absent; it may just mean that
+    /// the cache was locked at the time of the call.
+    pub fn try_get<B>(&self, k: &B) -> Option<CacheReadGuard<'_, V>>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        let found = if self.sliding {
+            let mut guard = self.store.try_write()?;
+            Self::find_mut(&mut guard, k)
+                .and_then(|(key, entry)| Some((key, unpack!(entry)?)))
+                .map(|(key, valid)| {
+                    self.slide(key, valid);
+                    valid.touch(self.access());
+                    CacheReadGuard {
+                        entry: valid as *const CacheEntry<V>,
+                        marker: PhantomData,
+                    }
+                })
+        } else {
+            let guard = self.store.try_read()?;
+            guard.get(k).and_then(|entry| unpack!(entry)).map(|valid| {
+                valid.touch(self.access());
+                CacheReadGuard {
+                    entry: valid as *const CacheEntry<V>,
+                    marker: PhantomData,
+                }
+            })
+        };
+
+        self.counters.lookup(found.is_some());
+        found
+    }
+
+    /// Insert a key/value pair into the cache without waiting.
+    ///
+    /// This behaves identically to `Cache::insert`, except that the key and value
+    /// are given back inside an `Err` immediately if the cache is currently locked,
+    /// rather than waiting for the lock to be released.
+    pub fn insert_nowait<E>(&self, k: K, v: V, e: E) -> Result<Option<V>, (K, V)>
+    where
+        E: Into<CacheExpiration>,
+    {
+        let store = match self.store.try_write() {
+            Some(store) => store,
+            None => return Err((k, v)),
+        };
+
+        let entry = self.create(&k, v, e.into());
+        self.inserting(&k, &entry);
+
+        Ok(self
+            .insert_locked(store, k, entry)
+            .map(CacheEntry::into_inner))
+    }
+
+    /// Retrieve a `Stream` over clones of all unexpired entries inside the cache.
+    ///
+    /// Entries are yielded in key order, copied from the store in small chunks so
+    /// that the lock is not held for the lifetime of the stream. As the lock is
+    /// released between chunks, entries inserted or removed whilst the stream is
+    /// being consumed may or may not be yielded; each key is yielded at most once.
+    pub fn stream(&self) -> impl Stream<Item = (K, V)> + '_
+    where
+        V: Clone,
+    {
+        let state = (VecDeque::new(), None::<K>);
+
+        stream::unfold(state, move |(mut buffer, mut cursor)| async move {
+            while buffer.is_empty() {
+                let chunk: Vec<_> = {
+                    let store = self.store.read().await;
+                    let lower = match &cursor {
+                        Some(key) => Bound::Excluded(key),
+                        None => Bound::Unbounded,
+                    };
+
+                    store
+                        .range::<K, _>((lower, Bound::Unbounded))
+                        .take(STREAM_CHUNK_SIZE)
+                        .map(|(key, entry)| {
+                            let value = unpack!(entry).map(|entry| entry.value().clone());
+                            (key.clone(), value)
+                        })
+                        .collect()
+                };
+
+                // move the cursor past this chunk, or finish if there's nothing left
+                cursor = Some(chunk.last()?.0.clone());
+
+                buffer.extend(
+                    chunk
+                        .into_iter()
+                        .filter_map(|(key, value)| value.map(|value| (key, value))),
+                );
+            }
+
+            buffer.pop_front().map(|pair| (pair, (buffer, cursor)))
+        })
+    }
+
+    /// Subscribe to a `Stream` of events for changes made to the cache.
+    ///
+    /// An event is yielded for every insertion, in-place update (e.g. `Cache::update`)
+    /// and removal of an entry, with removals of expired entries yielded as
+    /// `CacheEvent::Expired`. Changes made through the guard of `Cache::get_mut` are
+    /// not reported.
+    ///
+    /// Events are published before the cache lock is released, so the events for a
+    /// key are always yielded in the same order as the changes were made. Each
+    /// subscriber buffers a bounded number of events; if a subscriber falls behind,
+    /// further events are dropped (and counted via `EventStream::dropped`) rather
+    /// than blocking the cache. The stream ends once the cache has been dropped.
+    pub fn subscribe(&self) -> EventStream<K> {
+        self.subscribers.subscribe()
+    }
+
+    /// Retrieve the time remaining before an entry in the cache expires.
+    ///
+    /// This mirrors the `TTL` command in Redis; the outer `Option` represents
+    /// whether an unexpired entry exists for the key, while the inner `Option`
+    /// is `None` for entries inserted without an expiration. Callers which don't
+    /// need to distinguish these cases can use `Option::flatten` on the result.
+    pub async fn ttl<B>(&self, k: &B) -> Option<Option<Duration>>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        let guard = self.store.read().await;
+        let found = guard.get(k)?;
+        let valid = unpack!(found)?;
+
+        Some(valid.remaining())
+    }
+
+    /// Retrieve the number of unexpired entries inside the cache.
+    ///
+    /// Note that this is calculated by walking the set of entries and
+    /// should therefore not be used in performance sensitive situations.
+    pub async fn unexpired(&self) -> usize {
+        self.store
+            .read()
+            .await
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired())
+            .count()
+    }
+
+    /// Retrieve an estimate of the number of unexpired entries inside the cache.
+    ///
+    /// Unlike `Cache::unexpired`, this is a cheap read of a counter and never takes
+    /// the cache lock. The estimate is exact with respect to insertions and removals,
+    /// but may over-count by the number of entries which have expired and not been
+    /// accessed since; see `Cache::expired_estimate` for further details.
+    pub fn unexpired_estimate(&self) -> usize {
+        self.size
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.stale.load(Ordering::Relaxed))
+    }
+
+    /// Retrieve the total weight of all entries inside the cache.
+    ///
+    /// This *does* include entries which may be expired but are not yet evicted.
+    /// If no weigher has been set via `Cache::with_weigher`, each entry weighs `1`.
+    pub fn weight(&self) -> usize {
+        self.weight.load(Ordering::Relaxed)
+    }
+
+    /// Updates an entry in the cache without changing the expiration.
+    ///
+    /// The result of the provided closure is returned if an unexpired entry was
+    /// found and updated, otherwise `None` is returned.
+    pub async fn update<B, F, R>(&self, k: &B, f: F) -> Option<R>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+        F: FnOnce(&mut V) -> R,
+    {
+        let mut guard = self.store.write().await;
+        let (key, found) = Self::find_mut(&mut guard, k)?;
+        let valid = unpack!(found)?;
+        let result = f(valid.value_mut());
+
+        self.refresh(key, valid);
+
+        self.announce(key, CacheEvent::Updated);
+        drop(guard);
+
+        Some(result)
+    }
+
+    /// Updates an entry in the cache using an asynchronous closure.
+    ///
+    /// To avoid holding the cache lock while the closure runs, the value is cloned
+    /// out of the cache and passed to the closure, and the returned value is then
+    /// written back. If the entry was removed, replaced or expired while the closure
+    /// was running, the returned value is discarded and `false` is returned.
+    ///
+    /// Note that any in-place changes made to the entry whilst the closure is being
+    /// run (e.g. via `Cache::update`) will be overwritten by the returned value.
+    pub async fn update_async<B, F, Fut>(&self, k: &B, f: F) -> bool
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+        V: Clone,
+        F: FnOnce(V) -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let (value, created) = {
+            let guard = self.store.read().await;
+            match guard.get(k).and_then(|entry| unpack!(entry)) {
+                Some(entry) => (entry.value().clone(), entry.created()),
+                None => return false,
+            }
+        };
+
+        let value = f(value).await;
+        let mut guard = self.store.write().await;
+
+        match Self::find_mut(&mut guard, k) {
+            Some((key, entry)) if entry.created() == created && !entry.is_expired() => {
+                *entry.value_mut() = value;
+                self.refresh(key, entry);
+
+                self.announce(key, CacheEvent::Updated);
+                drop(guard);
+
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Updates an entry in the cache, or inserts a new entry if none exists.
+    ///
+    /// If an unexpired entry exists, the `update` closure is run against it and the
+    /// expiration is left unchanged (matching `Cache::update`). Otherwise the value
+    /// produced by the `insert` closure is inserted with the provided expiration.
+    /// The whole operation happens under a single write lock.
+    pub async fn update_or_insert<E, F, G>(&self, k: K, update: F, insert: G, e: E)
+    where
+        E: Into<CacheExpiration>,
+        F: FnOnce(&mut V),
+        G: FnOnce() -> V,
+    {
+        let mut store = self.store.write().await;
+
+        if let Some(entry) = store.get_mut(&k).and_then(|entry| unpack!(entry)) {
+            update(entry.value_mut());
+            self.refresh(&k, entry);
+
+            self.announce(&k, CacheEvent::Updated);
+            drop(store);
+            return;
+        }
+
+        let entry = self.create(&k, insert(), e.into());
+        self.inserting(&k, &entry);
+
+        let inserted = self.put(&mut store, k, entry);
+
+        drop(store);
+        self.settle(inserted);
+    }
+
+    /// Retrieve clones of all unexpired values inside the cache.
+    ///
+    /// Values are cloned in key order under a single read lock, which is released
+    /// before the values are returned. Entries which have expired but have not yet
+    /// been evicted are excluded, matching `Cache::get`.
+    pub async fn values_cloned(&self) -> Vec<V>
+    where
+        V: Clone,
+    {
+        self.store
+            .read()
+            .await
+            .values()
+            .filter(|entry| !entry.is_expired())
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    /// Reserve space for a key inside the store, based on the cache capacity.
+    ///
+    /// Entries are evicted whilst the store is at capacity (and the key is not
+    /// already stored), or whilst the total weight of the store would exceed the
+    /// maximum weight after inserting an entry of the provided weight. Each entry
+    /// evicted is selected via `Cache::victim`, starting with the provided victim
+    /// (if any) which was already selected when admitting the key. Evicted entries
+    /// are returned, so that listeners can be notified after the lock has been
+    /// released.
+    fn reserve(
+        &self,
+        store: &mut BTreeMap<K, CacheEntry<V>>,
+        k: &K,
+        weight: usize,
+        mut victim: Option<K>,
+    ) -> Vec<(K, CacheEntry<V>, EvictionCause)> {
+        let mut evicted = Vec::new();
+        let existing = store.get(k).map(CacheEntry::weight);
+
+        loop {
+            let full = self.capacity != 0 && existing.is_none() && store.len() >= self.capacity;
+            let heavy = self.max_weight != 0
+                && self.weight() - existing.unwrap_or(0) + weight > self.max_weight;
+
+            if !full && !heavy {
+                break;
+            }
+
+            let victim = victim.take().or_else(|| self.victim(store, k));
+
+            let (key, entry) = match victim.and_then(|victim| store.remove_entry(&victim)) {
+                Some(pair) => pair,
+                None => break,
+            };
+
+            self.unindex(&key, &entry);
+            self.reweigh(Some(&entry), None);
+            self.counters.evicted(1);
+
+            evicted.push((key, entry, EvictionCause::Capacity));
+        }
+
+        evicted
+    }
+
+    /// Select the entry to evict to make room for a key inside a locked store.
+    ///
+    /// Rather than walking the entire store, `VICTIM_SAMPLE_SIZE` entries are sampled
+    /// at random (plus one, in case the key itself is sampled); expired entries are
+    /// always selected first, followed by the least recently used entry of the
+    /// sample. If enabled via `Cache::with_max_entries`, the entry of the sample
+    /// with the soonest deadline is selected before the least recently used (so
+    /// entries without a deadline are selected last). Selection is exact whilst
+    /// the store is no larger than the sample. The key itself is never selected.
+    fn victim(&self, store: &BTreeMap<K, CacheEntry<V>>, k: &K) -> Option<K> {
+        let total = store.len();
+
+        // sample one extra entry, in case the key itself is sampled
+        let sample = cmp::min(VICTIM_SAMPLE_SIZE + 1, total);
+
+        // fetch `sample` distinct indices at random, in ascending order
+        let mut indices = index::sample(&mut rand::thread_rng(), total, sample).into_vec();
+        indices.sort_unstable();
+
+        // single iterator to allow us to iterate a single time for all indices
+        let mut iter = store.iter();
+        let mut next = 0;
+
+        indices
+            .into_iter()
+            .map(|idx| {
+                let pair = iter.nth(idx - next).unwrap();
+                next = idx + 1;
+                pair
+            })
+            .filter(|(key, _)| *key != k)
+            .min_by_key(|(_, entry)| {
+                let deadline = entry.deadline().filter(|_| self.soonest);
+                (
+                    !entry.is_expired(),
+                    self.soonest && deadline.is_none(),
+                    deadline,
+                    entry.accessed(),
+                )
+            })
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Determine whether a new key should be admitted into a locked store.
+    ///
+    /// Keys are always admitted unless admission is enabled and the cache is full,
+    /// in which case the key must be more frequent than an unexpired victim. Any
+    /// victim selected is returned alongside the decision, so that it can be reused
+    /// when reserving space for the key.
+    fn admit(&self, store: &BTreeMap<K, CacheEntry<V>>, k: &K) -> (bool, Option<K>) {
+        let admission = match &self.admission {
+            Some(admission) => admission,
+            None => return (true, None),
+        };
+
+        if self.capacity == 0 || store.len() < self.capacity || store.contains_key(k) {
+            return (true, None);
+        }
+
+        let victim = match self.victim(store, k) {
+            Some(victim) => victim,
+            None => return (true, None),
+        };
+
+        let admitted = match store.get(&victim) {
+            Some(entry) if !entry.is_expired() => admission.admit(k, &victim),
+            _ => true,
+        };
+
+        (admitted, Some(victim))
+    }
+
+    /// Wake all callers waiting for a key to be inserted.
+    fn wake(&self, k: &K) {
+        let waiters = self
+            .waiting
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(k);
+
+        for waiter in waiters.into_iter().flatten() {
+            waiter.wake();
+        }
+    }
+
+    /// Record an access to a key for admission purposes (if enabled).
+    fn record(&self, k: &K) {
+        if let Some(admission) = &self.admission {
+            admission.record(k);
+        }
+    }
+
+    /// Record the insertion of a key for admission purposes (if enabled).
+    ///
+    /// Lookups can't record keys which are absent from the store, as only a borrowed
+    /// form of the key is available, so the insertion of an absent key also records
+    /// the miss which preceded it.
+    fn recorded(&self, store: &BTreeMap<K, CacheEntry<V>>, k: &K) {
+        if let Some(admission) = &self.admission {
+            admission.record(k);
+
+            if !store.contains_key(k) {
+                admission.record(k);
+            }
+        }
+    }
+
+    /// Hand back a loaded entry which was rejected by admission to the caller.
+    ///
+    /// The value is returned to the caller rather than stored, and any listener
+    /// is notified of the rejection as with `Cache::insert`.
+    fn reject(&self, k: K, entry: CacheEntry<V>) -> CacheLoadGuard<'_, V> {
+        self.counters.evicted(1);
+        self.notify(&k, &entry, EvictionCause::Capacity);
+
+        CacheLoadGuard::Rejected(entry.into_inner())
+    }
+
+    /// Insert a key/value pair into a locked store, returning any unexpired entry.
+    ///
+    /// The lock is released before any listeners are notified of entries removed
+    /// by the insertion.
+    fn insert_locked(
+        &self,
+        mut store: RwLockWriteGuard<'_, BTreeMap<K, CacheEntry<V>>>,
+        k: K,
+        entry: CacheEntry<V>,
+    ) -> Option<CacheEntry<V>> {
+        let inserted = self.put(&mut store, k, entry);
+
+        drop(store);
+
+        self.settle(inserted).and_then(|entry| unpack!(entry))
+    }
+
+    /// Insert an entry into a locked store, reserving space based on capacity.
+    ///
+    /// Any entries removed by the insertion are returned rather than dropped, so
+    /// that listeners can be notified once the lock has been released.
+    fn put(
+        &self,
+        store: &mut BTreeMap<K, CacheEntry<V>>,
+        k: K,
+        entry: CacheEntry<V>,
+    ) -> Inserted<K, V> {
+        self.recorded(store, &k);
+
+        let (admitted, victim) = self.admit(store, &k);
+
+        if !admitted {
+            self.counters.evicted(1);
+            self.announce_removal(&k, &entry, EvictionCause::Capacity);
+
+            return Inserted {
+                key: None,
+                evicted: vec![(k, entry, EvictionCause::Capacity)],
+                displaced: None,
+                risen: None,
+            };
+        }
+
+        let len = store.len();
+        let evicted = self.reserve(store, &k, entry.weight(), victim);
+
+        for (key, entry, cause) in &evicted {
+            self.announce_removal(key, entry, *cause);
+        }
+
+        // only clone the key when there's a listener to notify
+        let key = if self.observed() {
+            Some(k.clone())
+        } else {
+            None
+        };
+
+        if let Some(existing) = store.get(&k) {
+            self.unindex(&k, existing);
+        }
+
+        self.reweigh(None, Some(&entry));
+        self.index(&k, &entry);
+        self.wake(&k);
+        let displaced = store.insert(k, entry);
+
+        self.reweigh(displaced.as_ref(), None);
+
+        if let Some(key) = &key {
+            if let Some(entry) = &displaced {
+                self.announce_removal(key, entry, EvictionCause::Replaced);
+            }
+            self.announce(key, CacheEvent::Inserted);
+        }
+
+        Inserted {
+            key,
+            evicted,
+            displaced,
+            risen: self.risen(len, store.len()),
+        }
+    }
+
+    /// Determine whether the cache has grown past the high water mark (if any).
+    fn risen(&self, before: usize, after: usize) -> Option<usize> {
+        match &self.high_water {
+            Some((mark, _)) if before <= *mark && after > *mark => Some(after),
+            _ => None,
+        }
+    }
+
+    /// Notify the high water mark listener (if any) that the cache has grown past the mark.
+    fn rise(&self, risen: Option<usize>) {
+        if let (Some(len), Some((_, listener))) = (risen, &self.high_water) {
+            listener(len);
+        }
+    }
+
+    /// Notify the eviction listener (if any) of entries removed by an insertion.
+    ///
+    /// The high water mark listener (if any) is also notified, if necessary (any
+    /// subscribers were already notified by `Cache::put`). Expired entries are
+    /// then published to the expired channel (if any); the displaced entry is only
+    /// returned if it was not published.
+    fn settle(&self, inserted: Inserted<K, V>) -> Option<CacheEntry<V>> {
+        self.rise(inserted.risen);
+
+        for (key, entry, cause) in &inserted.evicted {
+            self.notify(key, entry, *cause);
+        }
+
+        if let (Some(key), Some(entry)) = (&inserted.key, &inserted.displaced) {
+            self.notify(key, entry, EvictionCause::Replaced);
+        }
+
+        for (key, entry, cause) in inserted.evicted {
+            self.release(key, entry, cause);
+        }
+
+        // expired entries replaced by the insertion are released, rather than returned
+        match (inserted.key, inserted.displaced) {
+            (Some(key), Some(entry)) if entry.is_expired() => {
+                self.release(key, entry, EvictionCause::Expired);
+                None
+            }
+            (_, displaced) => displaced,
+        }
+    }
+
+    /// Notify the eviction listener (if any) of an entry evicted from the cache.
+    ///
+    /// Subscribers are notified separately via `Cache::announce_removal`, as that
+    /// has to happen whilst the write lock is still held.
+    fn notify(&self, key: &K, entry: &CacheEntry<V>, cause: EvictionCause) {
+        let cause = if entry.is_expired() {
+            EvictionCause::Expired
+        } else {
+            cause
+        };
+
+        if let Some(listener) = &self.listener {
+            listener(key, entry.value(), cause);
+        }
+
+        if let Some(hook) = &self.on_remove {
+            hook(key, entry.value());
+        }
+    }
+
+    /// Publish an event for a key to all subscribers (if any).
+    ///
+    /// The key is only cloned if there are subscribers to receive the event. Events
+    /// are published whilst the write lock is held, so that subscribers receive the
+    /// events for a key in the same order as the changes were made to the cache.
+    fn announce<F>(&self, key: &K, event: F)
+    where
+        F: FnOnce(K) -> CacheEvent<K>,
+    {
+        if !self.subscribers.is_empty() {
+            self.subscribers.publish(event(key.clone()));
+        }
+    }
+
+    /// Publish the removal of an entry to all subscribers (if any).
+    ///
+    /// Entries which have expired are published as `CacheEvent::Expired`, and any
+    /// unexpired entries replaced by an insertion aren't published at all.
+    fn announce_removal(&self, key: &K, entry: &CacheEntry<V>, cause: EvictionCause) {
+        match cause {
+            _ if entry.is_expired() => self.announce(key, CacheEvent::Expired),
+            EvictionCause::Replaced => (),
+            _ => self.announce(key, CacheEvent::Removed),
+        }
+    }
+
+    /// Clone a key to publish an event once it has been moved into the store, if there are subscribers.
+    fn watch(&self, key: &K) -> Option<K> {
+        if self.subscribers.is_empty() {
+            None
+        } else {
+            Some(key.clone())
+        }
+    }
+
+    /// Publish an event for a key cloned via `watch` to all subscribers (if any).
+    fn emit<F>(&self, key: Option<K>, event: F)
+    where
+        F: FnOnce(K) -> CacheEvent<K>,
+    {
+        if let Some(key) = key {
+            self.subscribers.publish(event(key));
+        }
+    }
+
+    /// Determine whether a listener, hook or subscriber is observing removals from the cache.
+    fn observed(&self) -> bool {
+        self.listener.is_some()
+            || self.owned_listener.is_some()
+            || self.on_remove.is_some()
+            || !self.subscribers.is_empty()
+            || self.publishing()
+    }
+
+    /// Call the insertion hook (if any) for an entry about to be stored.
+    fn inserting(&self, k: &K, entry: &CacheEntry<V>) {
+        if let Some(hook) = &self.on_insert {
+            hook(k, entry.value());
+        }
+    }
+
+    /// Release an entry dropped by the cache, passing the value on to be owned elsewhere.
+    ///
+    /// Expired entries are published to the expired channel (if any), and any entry
+    /// which was not published is passed to the owned eviction listener (if any).
+    fn release(&self, key: K, entry: CacheEntry<V>, cause: EvictionCause) {
+        let released = self.publish(key, entry);
+
+        if let (Some(listener), Some((key, entry))) = (&self.owned_listener, released) {
+            let cause = if entry.is_expired() {
+                EvictionCause::Expired
+            } else {
+                cause
+            };
+
+            listener(key, entry.into_inner(), cause);
+        }
+    }
+
+    /// Publish an entry to the expired channel (if any), if it has expired.
+    ///
+    /// The entry is handed back if it was not published.
+    #[cfg(feature = "async-channel")]
+    fn publish(&self, key: K, entry: CacheEntry<V>) -> Option<(K, CacheEntry<V>)> {
+        let sender = match &self.expired {
+            Some((sender, _)) if entry.is_expired() => sender,
+            _ => return Some((key, entry)),
+        };
+
+        if let Err(TrySendError::Full(_)) = sender.try_send((key, entry.into_inner())) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+
+        None
+    }
+
+    /// Publish an entry to the expired channel (if any), if it has expired.
+    ///
+    /// The entry is handed back if it was not published.
+    #[cfg(not(feature = "async-channel"))]
+    fn publish(&self, key: K, entry: CacheEntry<V>) -> Option<(K, CacheEntry<V>)> {
+        Some((key, entry))
+    }
+
+    /// Determine whether expired entries are published to an expired channel.
+    #[cfg(feature = "async-channel")]
+    fn publishing(&self) -> bool {
+        self.expired.is_some()
+    }
+
+    /// Determine whether expired entries are published to an expired channel.
+    #[cfg(not(feature = "async-channel"))]
+    fn publishing(&self) -> bool {
+        false
+    }
+
+    /// Drop entries removed from the cache, releasing each of them via `Cache::release`.
+    fn discard<I>(&self, removed: I, cause: EvictionCause)
+    where
+        I: IntoIterator<Item = (K, CacheEntry<V>)>,
+    {
+        for (key, entry) in removed {
+            self.release(key, entry, cause);
+        }
+    }
+
+    /// Remove all entries matching a predicate from the cache under a single lock.
+    ///
+    /// See `Cache::remove_selected` for further details on how entries are removed.
+    async fn remove_where<F>(&self, cause: EvictionCause, mut f: F) -> Vec<(K, CacheEntry<V>)>
+    where
+        F: FnMut(&K, &CacheEntry<V>) -> bool,
+    {
+        self.remove_selected(cause, |store| {
+            store
+                .iter()
+                .filter(|(key, entry)| f(key, entry))
+                .map(|(key, _)| key.clone())
+                .collect()
+        })
+        .await
+    }
+
+    /// Remove all entries for a selection of keys from the cache under a single lock.
+    ///
+    /// Keys are selected under an upgradable read lock, which is only upgraded to
+    /// a write lock to remove the selected entries; this allows readers to continue
+    /// whilst the cache is being walked. Listeners are notified of all removed entries
+    /// after the lock has been released, and the removed entries are then returned.
+    async fn remove_selected<F>(&self, cause: EvictionCause, select: F) -> Vec<(K, CacheEntry<V>)>
+    where
+        F: FnOnce(&BTreeMap<K, CacheEntry<V>>) -> Vec<K>,
+    {
+        let store = self.store.upgradable_read().await;
+        let keys = select(&store);
+
+        if keys.is_empty() {
+            return Vec::new();
+        }
+
+        let mut store = RwLockUpgradableReadGuard::upgrade(store).await;
+        let removed: Vec<_> = keys
+            .iter()
+            .filter_map(|key| store.remove_entry(key))
+            .collect();
+
+        for (key, entry) in &removed {
+            self.unindex(key, entry);
+        }
+
+        self.reweigh(removed.iter().map(|(_, entry)| entry), None);
+
+        for (key, entry) in &removed {
+            self.announce_removal(key, entry, cause);
+        }
+
+        drop(store);
+
+        for (key, entry) in &removed {
+            self.notify(key, entry, cause);
+        }
+
+        removed
+    }
+
+    /// Iterate all entries inside a locked store with keys starting with a prefix.
+    ///
+    /// Keys sharing a prefix are contiguous, so this starts at the prefix itself and
+    /// stops at the first key without the prefix, rather than calculating an upper
+    /// bound; this also handles prefixes at the very end of the key space.
+    fn prefixed<'s>(
+        store: &'s BTreeMap<K, CacheEntry<V>>,
+        prefix: &'s str,
+    ) -> impl Iterator<Item = (&'s K, &'s CacheEntry<V>)>
+    where
+        K: Borrow<str>,
+    {
+        store
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |(key, _)| (*key).borrow().starts_with(prefix))
+    }
+
+    /// Pop entries from the store until an unexpired entry is found.
+    async fn pop<F>(&self, f: F) -> Option<(K, V)>
+    where
+        F: Fn(&mut BTreeMap<K, CacheEntry<V>>) -> Option<(K, CacheEntry<V>)>,
+    {
+        let mut store = self.store.write().await;
+        let mut expired = Vec::new();
+
+        let popped = loop {
+            let (key, entry) = match f(&mut store) {
+                Some(pair) => pair,
+                None => break None,
+            };
+
+            self.unindex(&key, &entry);
+            self.reweigh(Some(&entry), None);
+
+            if !entry.is_expired() {
+                break Some((key, entry));
+            }
+
+            expired.push((key, entry));
+        };
+
+        for (key, entry) in &expired {
+            self.announce_removal(key, entry, EvictionCause::Expired);
+        }
+
+        if let Some((key, entry)) = &popped {
+            self.announce_removal(key, entry, EvictionCause::Manual);
+        }
+
+        drop(store);
+
+        self.counters.evicted(expired.len() as u64);
+
+        for (key, entry) in &expired {
+            self.notify(key, entry, EvictionCause::Expired);
+        }
+
+        self.discard(expired, EvictionCause::Expired);
+
+        let (key, entry) = popped?;
+        self.notify(&key, &entry, EvictionCause::Manual);
+
+        Some((key, entry.into_inner()))
+    }
+
+    /// Push the expiration of an entry forward, if sliding expiration is enabled.
+    fn slide(&self, k: &K, entry: &mut CacheEntry<V>) {
+        if !self.sliding {
+            return;
+        }
+
+        let duration = match self.rearm {
+            Some(ttl) if entry.expiration().instant().is_some() => Some(ttl),
+            Some(_) => None,
+            None => *entry.expiration().duration(),
+        };
+
+        if let Some(duration) = duration {
+            Self::rearm(entry, duration);
+            self.index(k, entry);
+        }
+    }
+
+    /// Reset the expiration of an entry to the provided duration from now.
+    fn rearm(entry: &mut CacheEntry<V>, duration: Duration) {
+        let mut expiration = CacheExpiration::from(duration);
+
+        // idle timeouts are carried across to the new expiration
+        if let Some(idle) = *entry.expiration().idle() {
+            expiration = expiration.with_idle(idle);
+        }
+
+        entry.set_expiration(expiration);
+    }
+
+    /// Remove an entry from the cache and notify the eviction listener (if any).
+    async fn take<B>(&self, k: &B) -> Option<(K, CacheEntry<V>)>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        let mut store = self.store.write().await;
+        let (key, entry) = store.remove_entry(k)?;
+        self.unindex(&key, &entry);
+        self.reweigh(Some(&entry), None);
+        self.announce_removal(&key, &entry, EvictionCause::Manual);
+
+        drop(store);
+        self.notify(&key, &entry, EvictionCause::Manual);
+        Some((key, entry))
+    }
+
+    /// Call the removal hook (if any) for all entries taken from the cache.
+    fn drained(&self, entries: &BTreeMap<K, CacheEntry<V>>) {
+        if let Some(hook) = &self.on_remove {
+            for (key, entry) in entries {
+                hook(key, entry.value());
+            }
+        }
+    }
+
+    /// Take all entries from the cache by swapping the store for an empty store.
+    ///
+    /// Subscribers (if any) are notified of every removal before the lock is released.
+    async fn take_all(&self) -> BTreeMap<K, CacheEntry<V>> {
+        let mut store = self.store.write().await;
+        self.unindex_all();
+        self.reset();
+
+        let taken = mem::take(&mut *store);
+
+        if !self.subscribers.is_empty() {
+            for (key, entry) in &taken {
+                self.announce_removal(key, entry, EvictionCause::Cleared);
+            }
+        }
+
+        taken
+    }
+
+    /// Track the deadline of an entry in the precise expiration index (if any).
+    fn index(&self, k: &K, entry: &CacheEntry<V>) {
+        if let (Some(deadlines), Some(deadline)) = (&self.deadlines, entry.deadline()) {
+            Self::deadlines(deadlines).insert((deadline, k.clone()));
+        }
+    }
+
+    /// Stop tracking the deadline of an entry in the precise expiration index (if any).
+    ///
+    /// Deadlines which have since moved are left in the index, and will be pruned
+    /// lazily once they pass.
+    fn unindex(&self, k: &K, entry: &CacheEntry<V>) {
+        if let (Some(deadlines), Some(deadline)) = (&self.deadlines, entry.deadline()) {
+            Self::deadlines(deadlines).remove(&(deadline, k.clone()));
+        }
+    }
+
+    /// Stop tracking all deadlines in the precise expiration index (if any).
+    fn unindex_all(&self) {
+        if let Some(deadlines) = &self.deadlines {
+            Self::deadlines(deadlines).clear();
+        }
+    }
+
+    /// Lock the precise expiration index, ignoring any poisoning.
+    fn deadlines(
+        deadlines: &SyncMutex<BTreeSet<(Instant, K)>>,
+    ) -> MutexGuard<'_, BTreeSet<(Instant, K)>> {
+        deadlines.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Retrieve the next tick of the internal access clock.
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Retrieve the tick to record against an access to an entry.
+    ///
+    /// Access ticks are only used to select entries to evict from a bounded cache,
+    /// so unbounded caches read the clock rather than advancing it on every read.
+    fn access(&self) -> u64 {
+        if self.capacity == 0 && self.max_weight == 0 {
+            return self.clock.load(Ordering::Relaxed);
+        }
+        self.tick()
+    }
+
+    /// Create a new entry for a key, applying any expiry jitter to the expiration.
+    fn create(&self, k: &K, v: V, e: CacheExpiration) -> CacheEntry<V> {
+        self.build(k, v, self.jitter(e))
+    }
+
+    /// Build a new entry for a key with an exact expiration, calculating the weight of the entry.
+    fn build(&self, k: &K, v: V, e: CacheExpiration) -> CacheEntry<V> {
+        let weight = self.weigh(k, &v);
+        CacheEntry::new(v, e, weight, self.tick())
+    }
+
+    /// Add a random offset to an expiration, if expiry jitter is enabled.
+    fn jitter(&self, e: CacheExpiration) -> CacheExpiration {
+        let max = match self.jitter {
+            Some(max) if e.instant().is_some() && max.as_nanos() > 0 => max,
+            _ => return e,
+        };
+
+        let nanos = rand::thread_rng().gen_range(0..max.as_nanos());
+        let offset = Duration::from_nanos(nanos as u64);
+
+        e.extend(offset).unwrap_or(e)
+    }
+
+    /// Locate a key and mutable entry inside a locked store.
+    fn find_mut<'s, B>(
+        store: &'s mut BTreeMap<K, CacheEntry<V>>,
+        k: &B,
+    ) -> Option<(&'s K, &'s mut CacheEntry<V>)>
+    where
+        K: Borrow<B>,
+        B: Ord + ?Sized,
+    {
+        store
+            .range_mut::<B, _>((Bound::Included(k), Bound::Included(k)))
+            .next()
+    }
+
+    /// Re-calculate the weight of an entry after it has been modified.
+    fn refresh(&self, k: &K, entry: &mut CacheEntry<V>) {
+        let weight = self.weigh(k, entry.value());
+        let previous = entry.set_weight(weight);
+
+        self.weight.fetch_add(weight, Ordering::Relaxed);
+        self.weight.fetch_sub(previous, Ordering::Relaxed);
+    }
+
+    /// Adjust the tracked totals of the cache as entries are added or removed.
+    fn reweigh<'e, R, A>(&self, removed: R, added: A)
+    where
+        R: IntoIterator<Item = &'e CacheEntry<V>>,
+        A: IntoIterator<Item = &'e CacheEntry<V>>,
+        V: 'e,
+    {
+        for entry in added {
+            self.weight.fetch_add(entry.weight(), Ordering::Relaxed);
+            self.size.fetch_add(1, Ordering::Relaxed);
+        }
+
+        for entry in removed {
+            self.weight.fetch_sub(entry.weight(), Ordering::Relaxed);
+            self.size.fetch_sub(1, Ordering::Relaxed);
+
+            if entry.is_stale() {
+                self.stale.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Reset the tracked totals of the cache once all entries have been removed.
+    fn reset(&self) {
+        self.weight.store(0, Ordering::Relaxed);
+        self.size.store(0, Ordering::Relaxed);
+        self.stale.store(0, Ordering::Relaxed);
+    }
+
+    /// Calculate the weight of an entry, using the weigher if one is set.
+    fn weigh(&self, k: &K, v: &V) -> usize {
+        self.weigher.as_ref().map_or(1, |weigher| weigher(k, v))
+    }
+}
+
+/// Entries removed from the store by an insertion.
+///
+/// The key of the displaced entry is only retained if there is a listener or
+/// subscriber attached to the cache, to avoid unnecessary cloning.
+struct Inserted<K, V> {
+    key: Option<K>,
+    evicted: Vec<(K, CacheEntry<V>, EvictionCause)>,
+    displaced: Option<CacheEntry<V>>,
+    risen: Option<usize>,
+}
+
+/// Tracking structure for an in-flight load of a key.
+///
+/// All callers loading the same key share the same lock, and the final
+/// caller to drop their flight will remove the lock from the tracking map.
+struct Flight<'a, K>
+where
+    K: Ord,
+{
+    key: K,
+    lock: Option<Arc<Mutex<()>>>,
+    loading: &'a SyncMutex<BTreeMap<K, Arc<Mutex<()>>>>,
+}
+
+impl<'a, K> Flight<'a, K>
+where
+    K: Ord + Clone,
+{
+    /// Join (or begin) the in-flight load for a key.
+    fn new(loading: &'a SyncMutex<BTreeMap<K, Arc<Mutex<()>>>>, key: K) -> Self {
+        let lock = loading
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        Self {
+            key,
+            lock: Some(lock),
+            loading,
+        }
+    }
+
+    /// Wait to acquire the shared lock of the in-flight load.
+    async fn lock(&self) -> async_lock::MutexGuard<'_, ()> {
+        self.lock.as_ref().expect("flight lock").lock().await
+    }
+}
+
+impl<'a, K> Drop for Flight<'a, K>
+where
+    K: Ord,
+{
+    // Removes the shared lock once there are no more callers waiting.
+    fn drop(&mut self) {
+        let mut flights = self.loading.lock().unwrap_or_else(|err| err.into_inner());
+
+        // release our reference whilst the map is locked, so that concurrent drops
+        // always see an accurate count of the remaining references
+        drop(self.lock.take());
+
+        // only the reference in the map remains
+        if flights
+            .get(&self.key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            flights.remove(&self.key);
+        }
+    }
+}
+
+/// Registration of a caller waiting for a key to be inserted.
+///
+/// Insertions remove all registrations for a key when waking them, otherwise
+/// the registration is removed from the tracking map once dropped.
+struct Waiting<'a, K>
+where
+    K: Ord,
+{
+    key: K,
+    waiter: Arc<Waiter>,
+    waiting: &'a SyncMutex<BTreeMap<K, Vec<Arc<Waiter>>>>,
+}
+
+impl<'a, K> Waiting<'a, K>
+where
+    K: Ord + Clone,
+{
+    /// Register a new waiter for a key.
+    fn new(waiting: &'a SyncMutex<BTreeMap<K, Vec<Arc<Waiter>>>>, key: K) -> Self {
+        let waiter = Arc::new(Waiter::default());
+
+        waiting
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .entry(key.clone())
+            .or_default()
+            .push(waiter.clone());
+
+        Self {
+            key,
+            waiter,
+            waiting,
+        }
+    }
+}
+
+impl<'a, K> Drop for Waiting<'a, K>
+where
+    K: Ord,
+{
+    // Removes the registration if it has not already been woken.
+    fn drop(&mut self) {
+        let mut waiting = self.waiting.lock().unwrap_or_else(|err| err.into_inner());
+
+        if let Some(waiters) = waiting.get_mut(&self.key) {
+            waiters.retain(|waiter| !Arc::ptr_eq(waiter, &self.waiter));
+
+            if waiters.is_empty() {
+                waiting.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Shared state between a waiting caller and the insertion which wakes it.
+#[derive(Default)]
+struct Waiter {
+    woken: AtomicBool,
+    waker: SyncMutex<Option<Waker>>,
+}
+
+impl Waiter {
+    /// Poll whether this waiter has been woken, registering the waker if not.
+    fn poll(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.woken.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+
+        *self.waker.lock().unwrap_or_else(|err| err.into_inner()) = Some(cx.waker().clone());
+
+        // check again in case we were woken before registering the waker
+        if self.woken.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+
+        Poll::Pending
+    }
+
+    /// Wake this waiter, and the task polling it (if any).
+    fn wake(&self) {
+        self.woken.store(true, Ordering::Release);
+
+        let waker = self
+            .waker
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take();
+
+        if let Some(waker) = waker {
+            waker.wake();
         }
     }
 }
@@ -311,3 +3503,92 @@ where
         Cache::new()
     }
 }
+
+/// Collection implementation.
+///
+/// This builds the store directly rather than going through the lock, so it can
+/// be used to synchronously construct a pre-populated cache. Entries are subject
+/// to the default (unbounded) capacity, as no limits can be configured yet.
+impl<K, V, E> FromIterator<(K, V, E)> for Cache<K, V>
+where
+    K: Ord + Clone,
+    E: Into<CacheExpiration>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V, E)>>(items: I) -> Self {
+        let mut cache = Cache::new();
+
+        for (key, value, expiration) in items {
+            let entry = cache.create(&key, value, expiration.into());
+            cache.reweigh(None, Some(&entry));
+
+            if let Some(displaced) = cache.store.get_mut().insert(key, entry) {
+                cache.reweigh(Some(&displaced), None);
+            }
+        }
+
+        cache
+    }
+}
+
+/// Serialization implementation.
+///
+/// The cache is serialized as a sequence of entries, each containing the key, the
+/// value and the remaining duration before expiration. Remaining durations are
+/// used rather than instants, as instants cannot be carried across processes.
+/// Entries which have already expired are not serialized.
+///
+/// As serialization cannot be done asynchronously, it never waits for the cache
+/// lock; if the cache is currently locked for writing (such as via a held
+/// `CacheWriteGuard`), serialization fails with a "cache is locked" error rather
+/// than blocking the current thread, and can be retried once the lock is free.
+#[cfg(feature = "serde")]
+impl<K, V> Serialize for Cache<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let store = self
+            .store
+            .try_read()
+            .ok_or_else(|| S::Error::custom("cache is locked"))?;
+
+        serializer.collect_seq(
+            store
+                .iter()
+                .filter(|(_, entry)| !entry.is_expired())
+                .map(|(key, entry)| (key, entry.value(), entry.remaining())),
+        )
+    }
+}
+
+/// Deserialization implementation.
+///
+/// Expirations are reconstructed relative to the time of deserialization, based
+/// on the remaining duration stored alongside each entry.
+#[cfg(feature = "serde")]
+impl<'de, K, V> Deserialize<'de> for Cache<K, V>
+where
+    K: Ord + Clone + Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let entries = Vec::<(K, V, Option<Duration>)>::deserialize(deserializer)?;
+
+        Ok(entries
+            .into_iter()
+            .map(|(key, value, remaining)| {
+                let expiration = remaining
+                    .map(CacheExpiration::from)
+                    .unwrap_or_else(CacheExpiration::none);
+                (key, value, expiration)
+            })
+            .collect())
+    }
+}
@@ -3,26 +3,121 @@
 //! Each entry has an associated value and optional expiration,
 //! and access functions for both. To be more convenient to the
 //! called, a `CacheEntry<V>` will also dereference to `V`.
+use std::cmp;
+use std::collections::BTreeMap;
 use std::marker::PhantomData;
-use std::ops::{Deref, Range};
-use std::time::{Duration, Instant};
+use std::mem;
+use std::ops::{Deref, DerefMut, Range};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
+use async_lock::RwLockWriteGuard;
 use rand::prelude::*;
 
+use crate::timer;
+
 /// Represents an entry inside the cache.
 ///
 /// Each entry has a value and optional expiration associated, with
 /// the value being seen through the `Deref` trait for convenience.
+///
+/// Entries also track the tick of their creation and last access; the
+/// former identifies an entry, while the latter is used to select entries
+/// for eviction when a cache has a bounded capacity. The weight of each
+/// entry is also stored, to avoid re-calculating it on removal, alongside
+/// any tags used to invalidate groups of entries.
+///
+/// In order to support idle timeouts, entries also track the time of their
+/// last access (as an offset from their time of creation). Entries which are
+/// found to have expired are flagged, to allow the cache to estimate the number
+/// of expired entries it contains without walking the store.
 #[derive(Debug)]
 pub(crate) struct CacheEntry<V> {
     value: V,
     expiration: CacheExpiration,
+    weight: usize,
+    tags: Vec<String>,
+    created: u64,
+    accessed: AtomicU64,
+    born: Instant,
+    touched: AtomicU64,
+    stale: AtomicBool,
 }
 
 impl<V> CacheEntry<V> {
-    /// Create a new cache entry from a value and expiration.
-    pub fn new(value: V, expiration: CacheExpiration) -> Self {
-        Self { value, expiration }
+    /// Create a new cache entry from a value, expiration, weight and access tick.
+    pub fn new(value: V, expiration: CacheExpiration, weight: usize, tick: u64) -> Self {
+        Self {
+            value,
+            expiration,
+            weight,
+            tags: Vec::new(),
+            created: tick,
+            accessed: AtomicU64::new(tick),
+            born: timer::now(),
+            touched: AtomicU64::new(0),
+            stale: AtomicBool::new(false),
+        }
+    }
+
+    /// Retrieve the tick this entry was created at.
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    /// Retrieve the tick of the last access to this entry.
+    pub fn accessed(&self) -> u64 {
+        self.accessed.load(Ordering::Relaxed)
+    }
+
+    /// Retrieve whether this entry has been flagged as expired.
+    pub fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::Relaxed)
+    }
+
+    /// Flag this entry as expired, returning whether it was previously unflagged.
+    pub fn mark_stale(&self) -> bool {
+        !self.stale.swap(true, Ordering::Relaxed)
+    }
+
+    /// Mark this entry as accessed at the provided tick.
+    ///
+    /// If the entry has an idle timeout, the time of the access is also tracked.
+    pub fn touch(&self, tick: u64) {
+        self.accessed.store(tick, Ordering::Relaxed);
+
+        if self.expiration.idle.is_some() {
+            let offset = timer::now().saturating_duration_since(self.born).as_nanos() as u64;
+            self.touched.store(offset, Ordering::Relaxed);
+        }
+    }
+
+    /// Retrieve the instant this entry will expire, considering any idle timeout.
+    pub fn deadline(&self) -> Option<Instant> {
+        let idle = self.expiration.idle.and_then(|idle| {
+            let touched = Duration::from_nanos(self.touched.load(Ordering::Relaxed));
+            self.born.checked_add(touched)?.checked_add(idle)
+        });
+
+        match (self.expiration.instant, idle) {
+            (Some(instant), Some(idle)) => Some(instant.min(idle)),
+            (instant, idle) => instant.or(idle),
+        }
+    }
+
+    /// Retrieve whether this entry has passed expiration.
+    ///
+    /// Unlike `CacheExpiration::is_expired`, this will also consider any idle timeout.
+    pub fn is_expired(&self) -> bool {
+        self.deadline()
+            .map(|deadline| deadline < timer::now())
+            .unwrap_or(false)
+    }
+
+    /// Retrieve the time remaining before expiration, considering any idle timeout.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline()
+            .map(|deadline| deadline.saturating_duration_since(timer::now()))
     }
 
     /// Retrieve the internal expiration.
@@ -35,6 +130,31 @@ impl<V> CacheEntry<V> {
         &self.value
     }
 
+    /// Retrieve the tags attached to this entry.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Retrieve the weight of this entry.
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// Replace the internal expiration, returning the previous expiration.
+    pub fn set_expiration(&mut self, expiration: CacheExpiration) -> CacheExpiration {
+        mem::replace(&mut self.expiration, expiration)
+    }
+
+    /// Replace the tags attached to this entry, returning the previous tags.
+    pub fn set_tags(&mut self, tags: Vec<String>) -> Vec<String> {
+        mem::replace(&mut self.tags, tags)
+    }
+
+    /// Replace the weight of this entry, returning the previous weight.
+    pub fn set_weight(&mut self, weight: usize) -> usize {
+        mem::replace(&mut self.weight, weight)
+    }
+
     /// Retrieve the mutable internal value.
     pub fn value_mut(&mut self) -> &mut V {
         &mut self.value
@@ -44,6 +164,11 @@ impl<V> CacheEntry<V> {
     pub fn into_inner(self) -> V {
         self.value
     }
+
+    /// Take the internal value and expiration.
+    pub fn into_parts(self) -> (V, CacheExpiration) {
+        (self.value, self.expiration)
+    }
 }
 
 /// Small structure to represent expiration in a cache.
@@ -55,16 +180,34 @@ impl<V> CacheEntry<V> {
 ///
 /// * `u64` -> a number of milliseconds to pass before an entry should expire.
 /// * `Instant` -> an exact time that an entry should expire.
+/// * `SystemTime` -> an exact wall-clock time that an entry should expire.
+/// * `DateTime<Utc>` -> as `SystemTime`, when the `chrono` feature is enabled.
 /// * `Duration` -> a duration to pass before an entry should expire.
 /// * `Range<u64>` -> a random range of milliseconds to sample from to
-///                   determine when an entry should expire.
+///   determine when an entry should expire.
 ///
 /// Other conversions may be added in future, but this should suffice for most
 /// cases. Any of these types may be passed to the insertion methods on a cache
 /// type when adding entries to a cache.
-#[derive(Debug)]
+///
+/// Wall-clock times are converted relative to the current time, and times in the
+/// past will produce an expiration which has already passed.
+///
+/// Times (or durations) too far in the future to be represented by an `Instant`
+/// will produce an empty expiration, rather than panicking.
+///
+/// Expirations created from a duration (i.e. all but the exact times) will also
+/// keep track of that duration, so that it can be re-applied at a later point.
+///
+/// Expirations are ordered by their instant, so an expiration which passes sooner
+/// will compare as less than one which passes later. Note that an empty expiration
+/// compares as less than any other expiration, as per the ordering of `Option`.
+/// Any tracked duration or idle timeout is ignored when comparing expirations.
+#[derive(Clone, Copy, Debug)]
 pub struct CacheExpiration {
     instant: Option<Instant>,
+    duration: Option<Duration>,
+    idle: Option<Duration>,
 }
 
 impl CacheExpiration {
@@ -75,12 +218,39 @@ impl CacheExpiration {
     {
         Self {
             instant: Some(instant.into()),
+            duration: None,
+            idle: None,
         }
     }
 
     /// Create an empty expiration (i.e. no expiration).
     pub fn none() -> Self {
-        Self { instant: None }
+        Self {
+            instant: None,
+            duration: None,
+            idle: None,
+        }
+    }
+
+    /// Attach an idle timeout to this expiration.
+    ///
+    /// Entries with an idle timeout will expire once they have not been accessed
+    /// for the provided duration, or once the instant of this expiration passes,
+    /// whichever comes first. Idle timeouts are tracked by the cache, so they are
+    /// not considered by `CacheExpiration::is_expired` or `remaining`.
+    pub fn with_idle(mut self, idle: Duration) -> Self {
+        self.idle = Some(idle);
+        self
+    }
+
+    /// Retrieve the duration this expiration was created from, if any.
+    pub fn duration(&self) -> &Option<Duration> {
+        &self.duration
+    }
+
+    /// Retrieve the idle timeout associated with this expiration, if any.
+    pub fn idle(&self) -> &Option<Duration> {
+        &self.idle
     }
 
     /// Retrieve the instant associated with this expiration.
@@ -91,14 +261,50 @@ impl CacheExpiration {
     /// Retrieve whether a cache entry has passed expiration.
     pub fn is_expired(&self) -> bool {
         self.instant()
-            .map(|expiration| expiration < Instant::now())
+            .map(|expiration| expiration < timer::now())
             .unwrap_or(false)
     }
 
     /// Retrieve the time remaining before expiration.
     pub fn remaining(&self) -> Option<Duration> {
         self.instant
-            .map(|i| i.saturating_duration_since(Instant::now()))
+            .map(|i| i.saturating_duration_since(timer::now()))
+    }
+
+    /// Create a new expiration by pushing this expiration further out.
+    ///
+    /// If there is no expiration, the duration is applied from the current time.
+    /// This will return `None` if the new instant cannot be represented.
+    pub(crate) fn extend(&self, by: Duration) -> Option<Self> {
+        self.instant
+            .unwrap_or_else(timer::now)
+            .checked_add(by)
+            .map(|instant| Self {
+                instant: Some(instant),
+                ..*self
+            })
+    }
+}
+
+// Expirations are equal when they pass at the same instant.
+impl PartialEq for CacheExpiration {
+    fn eq(&self, other: &Self) -> bool {
+        self.instant == other.instant
+    }
+}
+
+impl Eq for CacheExpiration {}
+
+// Expirations are ordered by the instant they pass.
+impl PartialOrd for CacheExpiration {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CacheExpiration {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.instant.cmp(&other.instant)
     }
 }
 
@@ -109,6 +315,30 @@ impl From<Instant> for CacheExpiration {
     }
 }
 
+// Automatic conversation from `SystemTime`.
+impl From<SystemTime> for CacheExpiration {
+    fn from(time: SystemTime) -> Self {
+        let now = timer::now();
+        let instant = match time.duration_since(SystemTime::now()) {
+            Ok(remaining) => now.checked_add(remaining),
+            Err(err) => Some(now.checked_sub(err.duration()).unwrap_or(now)),
+        };
+        Self {
+            instant,
+            duration: None,
+            idle: None,
+        }
+    }
+}
+
+// Automatic conversation from `DateTime<Utc>`.
+#[cfg(feature = "chrono")]
+impl From<chrono::DateTime<chrono::Utc>> for CacheExpiration {
+    fn from(time: chrono::DateTime<chrono::Utc>) -> Self {
+        SystemTime::from(time).into()
+    }
+}
+
 // Automatic conversation from `u64`.
 impl From<u64> for CacheExpiration {
     fn from(millis: u64) -> Self {
@@ -119,7 +349,11 @@ impl From<u64> for CacheExpiration {
 // Automatic conversation from `Duration`.
 impl From<Duration> for CacheExpiration {
     fn from(duration: Duration) -> Self {
-        Instant::now().checked_add(duration).unwrap().into()
+        Self {
+            instant: timer::now().checked_add(duration),
+            duration: Some(duration),
+            idle: None,
+        }
     }
 }
 
@@ -152,6 +386,22 @@ impl<'a, V> CacheReadGuard<'a, V> {
         self.entry().value()
     }
 
+    /// Retrieve whether the guarded entry has passed expiration.
+    ///
+    /// Guards returned by a cache lookup are never expired, so this is mainly
+    /// useful when inspecting a store via an `EvictionPolicy`.
+    pub fn is_expired(&self) -> bool {
+        self.entry().is_expired()
+    }
+
+    /// Retrieve the time remaining before the guarded entry expires.
+    ///
+    /// This is the canonical way to read the TTL of an entry alongside its
+    /// value, as it requires only the single lookup made by `Cache::get`.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.entry().remaining()
+    }
+
     /// Retrieve a reference to the internal entry.
     fn entry(&self) -> &CacheEntry<V> {
         unsafe { &*self.entry }
@@ -170,3 +420,116 @@ impl<'a, V> Deref for CacheReadGuard<'a, V> {
 // Stores a raw pointer to `T`, so if `T` is `Sync`, the lock guard over `T` is `Send`.
 unsafe impl<V> Send for CacheReadGuard<'_, V> where V: Sized + Sync {}
 unsafe impl<V> Sync for CacheReadGuard<'_, V> where V: Sized + Send + Sync {}
+
+/// Guard for values retrieved (or loaded) by `Cache::get_or_insert_with` and friends.
+///
+/// Loaded values are usually stored inside the cache, in which case this refers to
+/// the stored entry in the same way as a `CacheReadGuard`. A loaded value which was
+/// not admitted to the cache (such as a value rejected by `Cache::with_tinylfu`) is
+/// instead handed back as an owned value, so that it can still be returned. This
+/// structure implements `Deref` to convert itself into the inner value.
+#[derive(Debug)]
+pub enum CacheLoadGuard<'a, V> {
+    /// A value stored inside the cache.
+    Cached(CacheReadGuard<'a, V>),
+    /// A loaded value which was not admitted to the cache.
+    Rejected(V),
+}
+
+impl<'a, V> CacheLoadGuard<'a, V> {
+    /// Retrieve the internal guarded value.
+    pub fn value(&self) -> &V {
+        match self {
+            CacheLoadGuard::Cached(guard) => guard.value(),
+            CacheLoadGuard::Rejected(value) => value,
+        }
+    }
+
+    /// Retrieve whether the value was stored inside the cache.
+    pub fn is_cached(&self) -> bool {
+        matches!(self, CacheLoadGuard::Cached(_))
+    }
+}
+
+impl<'a, V> Deref for CacheLoadGuard<'a, V> {
+    type Target = V;
+
+    // Derefs a load guard to the internal value.
+    fn deref(&self) -> &Self::Target {
+        self.value()
+    }
+}
+
+/// Write guard for mutable references to the inner cache structure.
+///
+/// Unlike `CacheReadGuard`, this structure owns the write lock over the inner
+/// cache structure, which will be held until the guard is dropped. This allows
+/// mutation of the inner value via `DerefMut`, so callers should take care to
+/// drop the guard as soon as possible to avoid blocking other cache operations.
+#[derive(Debug)]
+pub struct CacheWriteGuard<'a, K, V> {
+    pub(crate) entry: *mut CacheEntry<V>,
+    pub(crate) _guard: RwLockWriteGuard<'a, BTreeMap<K, CacheEntry<V>>>,
+}
+
+impl<'a, K, V> CacheWriteGuard<'a, K, V> {
+    /// Retrieve the internal guarded expiration.
+    pub fn expiration(&self) -> &CacheExpiration {
+        self.entry().expiration()
+    }
+
+    /// Retrieve the internal guarded value.
+    pub fn value(&self) -> &V {
+        self.entry().value()
+    }
+
+    /// Retrieve the mutable internal guarded value.
+    pub fn value_mut(&mut self) -> &mut V {
+        self.entry_mut().value_mut()
+    }
+
+    /// Retrieve the time remaining before the guarded entry expires.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.entry().remaining()
+    }
+
+    /// Retrieve a reference to the internal entry.
+    fn entry(&self) -> &CacheEntry<V> {
+        unsafe { &*self.entry }
+    }
+
+    /// Retrieve a mutable reference to the internal entry.
+    fn entry_mut(&mut self) -> &mut CacheEntry<V> {
+        unsafe { &mut *self.entry }
+    }
+}
+
+impl<'a, K, V> Deref for CacheWriteGuard<'a, K, V> {
+    type Target = V;
+
+    // Derefs a cache guard to the internal entry.
+    fn deref(&self) -> &Self::Target {
+        self.value()
+    }
+}
+
+impl<'a, K, V> DerefMut for CacheWriteGuard<'a, K, V> {
+    // Derefs a cache guard to the mutable internal entry.
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.value_mut()
+    }
+}
+
+// Stores a raw pointer alongside the lock guard, so follows the same rules as the lock guard.
+unsafe impl<K, V> Send for CacheWriteGuard<'_, K, V>
+where
+    K: Send,
+    V: Send,
+{
+}
+unsafe impl<K, V> Sync for CacheWriteGuard<'_, K, V>
+where
+    K: Sync,
+    V: Sync,
+{
+}
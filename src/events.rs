@@ -0,0 +1,204 @@
+//! Small structures used to subscribe to changes made to a cache.
+//!
+//! Subscriptions are created via `Cache::subscribe`, and receive an event for
+//! every insertion, update and removal made to the cache. Events only carry
+//! the key of the affected entry, to avoid cloning values. Events are published
+//! whilst the cache lock is held, so events for the same key are always received
+//! in the order the changes were made. Each subscription buffers a bounded number
+//! of events, so a slow subscriber can never block (or grow) the cache; any events
+//! which don't fit are counted and dropped.
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use futures_lite::Stream;
+
+/// Number of events buffered for each subscription.
+const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// Enumeration of the changes which can be made to a cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CacheEvent<K> {
+    /// A value was inserted for the key, possibly replacing a previous value.
+    Inserted(K),
+    /// The value for the key was modified in place, or replaced via `Cache::replace`.
+    Updated(K),
+    /// The entry for the key was removed from the cache.
+    Removed(K),
+    /// The entry for the key was removed from the cache after expiring.
+    Expired(K),
+}
+
+/// Stream of events for changes made to a cache.
+///
+/// The stream ends once the cache has been dropped. Dropping the stream will
+/// remove the subscription from the cache on the next change to the cache.
+pub struct EventStream<K> {
+    channel: Arc<Channel<K>>,
+}
+
+impl<K> EventStream<K> {
+    /// Retrieve the number of events dropped due to a full buffer.
+    pub fn dropped(&self) -> u64 {
+        self.channel.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<K> Stream for EventStream<K> {
+    type Item = CacheEvent<K>;
+
+    // Pops the next buffered event, or waits for the next event to be sent.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.channel.state();
+
+        if let Some(event) = state.queue.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+
+        if state.finished {
+            return Poll::Ready(None);
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<K> Drop for EventStream<K> {
+    // Marks the channel as closed so that it can be removed from the cache.
+    fn drop(&mut self) {
+        self.channel.state().closed = true;
+    }
+}
+
+/// Tracking structure for all subscriptions attached to a cache.
+pub(crate) struct Subscribers<K> {
+    active: AtomicUsize,
+    channels: Mutex<Vec<Arc<Channel<K>>>>,
+}
+
+impl<K> Subscribers<K>
+where
+    K: Clone,
+{
+    /// Create a new `Subscribers` without any subscriptions.
+    pub fn new() -> Self {
+        Self {
+            active: AtomicUsize::new(0),
+            channels: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Check whether there are any subscriptions.
+    ///
+    /// This avoids locking, so that caches without subscriptions pay very little.
+    pub fn is_empty(&self) -> bool {
+        self.active.load(Ordering::Relaxed) == 0
+    }
+
+    /// Publish an event to all subscriptions, removing any which have been dropped.
+    pub fn publish(&self, event: CacheEvent<K>) {
+        if self.is_empty() {
+            return;
+        }
+
+        let mut channels = self.channels();
+
+        channels.retain(|channel| channel.send(&event));
+        self.active.store(channels.len(), Ordering::Relaxed);
+    }
+
+    /// Create a new subscription.
+    pub fn subscribe(&self) -> EventStream<K> {
+        let channel = Arc::new(Channel {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                waker: None,
+                closed: false,
+                finished: false,
+            }),
+            dropped: AtomicU64::new(0),
+        });
+
+        let mut channels = self.channels();
+
+        channels.push(channel.clone());
+        self.active.store(channels.len(), Ordering::Relaxed);
+
+        EventStream { channel }
+    }
+
+    /// Lock the list of subscriptions, ignoring any poisoning.
+    fn channels(&self) -> MutexGuard<'_, Vec<Arc<Channel<K>>>> {
+        self.channels.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<K> Drop for Subscribers<K> {
+    // Ends all streams once the cache has been dropped.
+    fn drop(&mut self) {
+        let channels = self
+            .channels
+            .get_mut()
+            .unwrap_or_else(|err| err.into_inner());
+
+        for channel in channels.drain(..) {
+            let mut state = channel.state();
+            state.finished = true;
+
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Shared state between the cache and a single subscription.
+struct Channel<K> {
+    state: Mutex<State<K>>,
+    dropped: AtomicU64,
+}
+
+/// Mutable state of a channel, guarded by a lock.
+struct State<K> {
+    queue: VecDeque<CacheEvent<K>>,
+    waker: Option<Waker>,
+    closed: bool,
+    finished: bool,
+}
+
+impl<K> Channel<K>
+where
+    K: Clone,
+{
+    /// Send an event to this channel, returning whether the channel is still open.
+    fn send(&self, event: &CacheEvent<K>) -> bool {
+        let mut state = self.state();
+
+        if state.closed {
+            return false;
+        }
+
+        if state.queue.len() >= EVENT_BUFFER_SIZE {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+
+        state.queue.push_back(event.clone());
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+
+        true
+    }
+}
+
+impl<K> Channel<K> {
+    /// Lock the state of this channel, ignoring any poisoning.
+    fn state(&self) -> MutexGuard<'_, State<K>> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
@@ -0,0 +1,183 @@
+//! Small structures used to observe entries being evicted from a cache.
+//!
+//! Listeners can be attached to a cache via `Cache::with_eviction_listener`,
+//! and will be provided with each evicted entry alongside the cause of the
+//! eviction. Listeners are called after the cache lock has been released,
+//! so it's safe for a listener to access the cache it's attached to.
+//!
+//! The entries removed by each purge of a cache are selected by an eviction
+//! policy, which can be replaced via `Cache::with_eviction_policy`. By default
+//! this is `RandomSampling`, which mirrors the expiration logic of Redis.
+use std::cmp;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use rand::rngs::StdRng;
+use rand::seq::index;
+use rand::SeedableRng;
+
+use crate::entry::{CacheEntry, CacheReadGuard};
+
+/// Enumeration of the reasons an entry can be evicted from a cache.
+///
+/// Any entry which had already expired when it was removed from the cache
+/// will be reported as `EvictionCause::Expired`, regardless of how it was
+/// actually removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EvictionCause {
+    /// The entry was removed after passing its expiration.
+    Expired,
+    /// The entry was replaced by the insertion of a new value.
+    Replaced,
+    /// The entry was manually removed from the cache.
+    Manual,
+    /// The entry was evicted to make room for another entry.
+    Capacity,
+    /// The entry was removed by clearing the cache.
+    Cleared,
+}
+
+/// Listener function type used to observe evicted entries.
+pub(crate) type EvictionListener<K, V> = Box<dyn Fn(&K, &V, EvictionCause) + Send + Sync>;
+
+/// Listener function type used to take ownership of evicted entries.
+pub(crate) type OwnedEvictionListener<K, V> = Box<dyn Fn(K, V, EvictionCause) + Send + Sync>;
+
+/// Hook function type used to observe entries being inserted into or removed from a cache.
+pub(crate) type Hook<K, V> = Box<dyn Fn(&K, &V) + Send + Sync>;
+
+/// Weigher function type used to calculate the weight of entries.
+pub(crate) type Weigher<K, V> = Box<dyn Fn(&K, &V) -> usize + Send + Sync>;
+
+/// Listener function type used to observe a cache growing past a high water mark.
+pub(crate) type WaterMarkListener = Box<dyn Fn(usize) + Send + Sync>;
+
+/// Channel type used to publish entries removed after expiring.
+#[cfg(feature = "async-channel")]
+pub(crate) type ExpiredChannel<K, V> = (
+    async_channel::Sender<(K, V)>,
+    async_channel::Receiver<(K, V)>,
+);
+
+/// Policy used to select the entries removed by each batch of a purge.
+///
+/// A policy is provided with a read-only view of the store, and returns the
+/// keys which should be removed from the store. Entries which have expired
+/// are reported as `EvictionCause::Expired` when removed, and any other entry
+/// is reported as `EvictionCause::Capacity`.
+pub trait EvictionPolicy<K, V>: Send + Sync {
+    /// Select the keys to remove from a batch of (up to) `sample` entries.
+    ///
+    /// The purge will continue with another batch if at least the configured
+    /// threshold of `sample` keys are returned (and then removed).
+    fn select(&self, store: StoreView<'_, K, V>, sample: usize) -> Vec<K>;
+}
+
+/// Read-only view of the store inside a cache.
+///
+/// This is provided to an `EvictionPolicy` whilst the cache is locked, so
+/// entries are exposed via `CacheReadGuard` in the same way as `Cache::get`.
+pub struct StoreView<'a, K, V> {
+    store: &'a BTreeMap<K, CacheEntry<V>>,
+}
+
+impl<'a, K, V> StoreView<'a, K, V> {
+    /// Construct a new `StoreView` around a store.
+    pub(crate) fn new(store: &'a BTreeMap<K, CacheEntry<V>>) -> Self {
+        Self { store }
+    }
+
+    /// Check whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    /// Retrieve the number of entries inside the store.
+    ///
+    /// This *does* include entries which may be expired but are not yet evicted.
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    /// Iterate all entries inside the store, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a K, CacheReadGuard<'a, V>)> + 'a {
+        self.store.iter().map(|(key, entry)| {
+            let guard = CacheReadGuard {
+                entry,
+                marker: PhantomData,
+            };
+            (key, guard)
+        })
+    }
+}
+
+/// Eviction policy based on random sampling of entries.
+///
+/// Each batch samples `sample` distinct entries at random, and selects any of
+/// them which have expired. This is the default policy of a cache, and is the
+/// same approach used by Redis to remove expired keys.
+#[derive(Debug, Default)]
+pub struct RandomSampling {
+    rng: Option<Mutex<StdRng>>,
+}
+
+impl RandomSampling {
+    /// Construct a new `RandomSampling` using a thread local generator.
+    pub fn new() -> Self {
+        Self { rng: None }
+    }
+
+    /// Construct a new `RandomSampling` using a seeded generator.
+    ///
+    /// Given the same store, each batch will sample the same entries; this is
+    /// mainly useful for testing.
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            rng: Some(Mutex::new(StdRng::seed_from_u64(seed))),
+        }
+    }
+}
+
+impl<K, V> EvictionPolicy<K, V> for RandomSampling
+where
+    K: Clone,
+{
+    fn select(&self, store: StoreView<'_, K, V>, sample: usize) -> Vec<K> {
+        let total = store.len();
+        let sample = cmp::min(sample, total);
+
+        // fetch `sample` distinct indices at random, in ascending order
+        let mut indices = match &self.rng {
+            Some(rng) => {
+                let mut rng = rng.lock().unwrap_or_else(|err| err.into_inner());
+                index::sample(&mut *rng, total, sample).into_vec()
+            }
+            None => index::sample(&mut rand::thread_rng(), total, sample).into_vec(),
+        };
+        indices.sort_unstable();
+
+        // single iterator to allow us to iterate a single time for all indices
+        let mut iter = store.iter();
+        let mut keys = Vec::new();
+
+        // tracker for the index of the next pair in the iterator
+        let mut next = 0;
+
+        // walk our index list
+        for idx in indices {
+            // fetch the pair at our index, skipping anything before it
+            let (key, entry) = iter.nth(idx - next).unwrap();
+
+            // mark the following index as the next pair
+            next = idx + 1;
+
+            // mark for removal if expired
+            if entry.is_expired() {
+                keys.push(key.to_owned());
+            }
+        }
+
+        keys
+    }
+}
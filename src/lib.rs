@@ -1,9 +1,23 @@
 #![doc = include_str!("../README.md")]
 
 // exposed modules
+pub mod builder;
 pub mod cache;
 pub mod entry;
+pub mod events;
+pub mod eviction;
+pub mod sharded;
+pub mod stats;
+
+// internal modules
+mod admission;
+mod timer;
 
 // lifted types to the top level
+pub use crate::builder::{BuildError, CacheBuilder};
 pub use crate::cache::Cache;
-pub use crate::entry::CacheExpiration;
+pub use crate::entry::{CacheExpiration, CacheLoadGuard, CacheReadGuard, CacheWriteGuard};
+pub use crate::events::{CacheEvent, EventStream};
+pub use crate::eviction::{EvictionCause, EvictionPolicy, RandomSampling};
+pub use crate::sharded::ShardedCache;
+pub use crate::stats::{CacheStats, PurgeReport};
@@ -0,0 +1,218 @@
+//! Sharded caching structures for use in write-heavy contexts.
+//!
+//! The `Cache` type stores all entries behind a single lock, which means that
+//! all writers are serialized. The `ShardedCache` type in this module instead
+//! spreads entries across several inner `Cache` instances, selected using the
+//! hash of each key, so that writers touching different keys don't contend.
+//!
+//! This comes at the cost of requiring `K: Hash`, and only a core subset of
+//! the `Cache` API is exposed; operations which need to look at the entire
+//! store (such as `len`) have to visit every shard.
+use std::borrow::Borrow;
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::time::Duration;
+
+use crate::cache::Cache;
+use crate::entry::{CacheExpiration, CacheReadGuard, CacheWriteGuard};
+use crate::timer::Interval;
+
+/// Caching structure spreading entries across several inner caches.
+///
+/// Each key is assigned to a shard based on its hash, and all operations on
+/// that key are delegated to the `Cache` representing that shard.
+pub struct ShardedCache<K, V> {
+    shards: Vec<Cache<K, V>>,
+    hasher: RandomState,
+}
+
+impl<K, V> ShardedCache<K, V>
+where
+    K: Ord + Clone + Hash,
+{
+    /// Construct a new `ShardedCache` with the provided number of shards.
+    ///
+    /// A sharded cache will always contain at least a single shard.
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..cmp::max(shards, 1)).map(|_| Cache::new()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Sets the label inside this cache for logging purposes.
+    ///
+    /// Each shard will be labelled with the provided label and its index.
+    pub fn with_label(self, s: &str) -> Self {
+        Self {
+            shards: self
+                .shards
+                .into_iter()
+                .enumerate()
+                .map(|(idx, shard)| shard.with_label(&format!("{}[{}]", s, idx)))
+                .collect(),
+            hasher: self.hasher,
+        }
+    }
+
+    /// Remove all entries from the cache.
+    pub async fn clear(&self) {
+        for shard in &self.shards {
+            shard.clear().await;
+        }
+    }
+
+    /// Check whether an unexpired entry exists for a key in the cache.
+    pub async fn contains_key<B>(&self, k: &B) -> bool
+    where
+        K: Borrow<B>,
+        B: Ord + Hash + ?Sized,
+    {
+        self.shard(k).contains_key(k).await
+    }
+
+    /// Retrieve the number of expired entries inside the cache.
+    ///
+    /// Note that this is calculated by walking the set of entries in every
+    /// shard and should therefore not be used in performance sensitive situations.
+    pub async fn expired(&self) -> usize {
+        let mut expired = 0;
+        for shard in &self.shards {
+            expired += shard.expired().await;
+        }
+        expired
+    }
+
+    /// Retrieve a reference to a value inside the cache.
+    pub async fn get<B>(&self, k: &B) -> Option<CacheReadGuard<'_, V>>
+    where
+        K: Borrow<B>,
+        B: Ord + Hash + ?Sized,
+    {
+        self.shard(k).get(k).await
+    }
+
+    /// Retrieve a mutable reference to a value inside the cache.
+    ///
+    /// The returned guard holds the write lock of the shard containing the key.
+    pub async fn get_mut<B>(&self, k: &B) -> Option<CacheWriteGuard<'_, K, V>>
+    where
+        K: Borrow<B>,
+        B: Ord + Hash + ?Sized,
+    {
+        self.shard(k).get_mut(k).await
+    }
+
+    /// Insert a key/value pair into the cache with an associated expiration.
+    pub async fn insert<E>(&self, k: K, v: V, e: E) -> Option<V>
+    where
+        E: Into<CacheExpiration>,
+    {
+        self.shard(&k).insert(k, v, e).await
+    }
+
+    /// Insert a key/value pair into the cache without an associated expiration.
+    pub async fn insert_untracked(&self, k: K, v: V) -> Option<V> {
+        self.shard(&k).insert_untracked(k, v).await
+    }
+
+    /// Check whether the cache is empty.
+    pub async fn is_empty(&self) -> bool {
+        for shard in &self.shards {
+            if !shard.is_empty().await {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Retrieve the number of entries inside the cache.
+    ///
+    /// This *does* include entries which may be expired but are not yet evicted.
+    pub async fn len(&self) -> usize {
+        let mut len = 0;
+        for shard in &self.shards {
+            len += shard.len().await;
+        }
+        len
+    }
+
+    /// Retrieve a `Future` used to monitor expired keys.
+    ///
+    /// This future must be spawned on whatever runtime you are using inside your
+    /// application; not doing this will result in keys never being expired.
+    ///
+    /// For expiration logic, please see `ShardedCache::purge`.
+    pub async fn monitor(&self, sample: usize, threshold: f64, frequency: Duration) {
+        let mut interval = Interval::new(frequency);
+        loop {
+            interval.tick().await;
+            self.purge(sample, threshold).await;
+        }
+    }
+
+    /// Cleanses the cache of expired entries.
+    ///
+    /// The `sample` is split across the shards in proportion to the number of
+    /// entries inside each shard, and then each shard is purged via `Cache::purge`.
+    pub async fn purge(&self, sample: usize, threshold: f64) {
+        let mut sizes = Vec::with_capacity(self.shards.len());
+        for shard in &self.shards {
+            sizes.push(shard.len().await);
+        }
+
+        let total: usize = sizes.iter().sum();
+        if total == 0 {
+            return;
+        }
+
+        for (shard, size) in self.shards.iter().zip(sizes) {
+            if size == 0 {
+                continue;
+            }
+            // widen to avoid overflowing with very large samples
+            let share = (sample as u128 * size as u128).div_ceil(total as u128) as usize;
+            shard.purge(share, threshold).await;
+        }
+    }
+
+    /// Remove an entry from the cache and return any stored value.
+    pub async fn remove<B>(&self, k: &B) -> Option<V>
+    where
+        K: Borrow<B>,
+        B: Ord + Hash + ?Sized,
+    {
+        self.shard(k).remove(k).await
+    }
+
+    /// Retrieve the time remaining before an entry in the cache expires.
+    pub async fn ttl<B>(&self, k: &B) -> Option<Option<Duration>>
+    where
+        K: Borrow<B>,
+        B: Ord + Hash + ?Sized,
+    {
+        self.shard(k).ttl(k).await
+    }
+
+    /// Retrieve the number of unexpired entries inside the cache.
+    ///
+    /// Note that this is calculated by walking the set of entries in every
+    /// shard and should therefore not be used in performance sensitive situations.
+    pub async fn unexpired(&self) -> usize {
+        let mut unexpired = 0;
+        for shard in &self.shards {
+            unexpired += shard.unexpired().await;
+        }
+        unexpired
+    }
+
+    /// Retrieve the shard responsible for a key.
+    fn shard<B>(&self, k: &B) -> &Cache<K, V>
+    where
+        B: Hash + ?Sized,
+    {
+        let hash = self.hasher.hash_one(k);
+        &self.shards[hash as usize % self.shards.len()]
+    }
+}
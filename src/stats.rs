@@ -0,0 +1,122 @@
+//! Small structures used to track statistics about cache usage.
+//!
+//! Statistics are tracked using relaxed atomic counters, so that they can
+//! be updated without requiring any additional locking inside the cache.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Snapshot of the statistics tracked by a cache.
+///
+/// This structure is retrieved via `Cache::stats`, and represents the
+/// state of the counters at the time of retrieval.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl CacheStats {
+    /// Retrieve the number of lookups which found an unexpired entry.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Retrieve the number of lookups which found no entry (or an expired entry).
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Retrieve the number of entries evicted by the cache.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// Retrieve the ratio of lookups which resulted in a hit.
+    ///
+    /// If no lookups have been made, this will return `0.0`.
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+}
+
+/// Report of the work done by a single purge of a cache.
+///
+/// This structure is retrieved via `Cache::last_purge`, and is replaced at
+/// the end of every call to `Cache::purge` (including those made by monitors).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PurgeReport {
+    pub(crate) removed: usize,
+    pub(crate) sampled: usize,
+    pub(crate) elapsed: Duration,
+    pub(crate) locked: Duration,
+}
+
+impl PurgeReport {
+    /// Retrieve the number of entries removed by the purge.
+    pub fn removed(&self) -> usize {
+        self.removed
+    }
+
+    /// Retrieve the number of entries sampled by the purge.
+    ///
+    /// When precise expiration is enabled, this is instead the number of deadlines
+    /// taken from the expiration index (including any which had since moved).
+    pub fn sampled(&self) -> usize {
+        self.sampled
+    }
+
+    /// Retrieve the total time taken by the purge.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Retrieve the time spent holding the write lock during the purge.
+    pub fn locked(&self) -> Duration {
+        self.locked
+    }
+}
+
+/// Atomic counters used to track statistics inside a cache.
+#[derive(Debug, Default)]
+pub(crate) struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl CacheCounters {
+    /// Record a lookup as either a hit or a miss.
+    pub fn lookup(&self, hit: bool) {
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record a number of evicted entries.
+    pub fn evicted(&self, count: u64) {
+        self.evictions.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Reset all counters back to zero.
+    pub fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        self.evictions.store(0, Ordering::Relaxed);
+    }
+
+    /// Retrieve a snapshot of the current counters.
+    pub fn snapshot(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
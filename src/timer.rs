@@ -0,0 +1,93 @@
+//! Small timer structures used to drive the background tasks of a cache.
+//!
+//! Timers are backed by `async-timer` by default. If the `tokio` feature is
+//! enabled, the timers provided by `tokio` are used instead; this integrates
+//! with the time controls of `tokio` in tests, but it does mean that monitors
+//! (and `Cache::wait_for`) must then be run inside a `tokio` runtime, and will
+//! panic otherwise. As features are unified across the dependency graph, this
+//! applies as soon as *any* crate enables the `tokio` feature, so it is not
+//! an additive feature; libraries should leave the choice to the application.
+//!
+//! With the `tokio` feature, the clock used to expire entries also follows the
+//! clock of `tokio` (falling back to the system clock outside of a runtime), so
+//! pausing or advancing time in `tokio` controls expiration as well.
+use std::future;
+#[cfg(not(feature = "tokio"))]
+use std::future::Future;
+#[cfg(not(feature = "tokio"))]
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+#[cfg(not(feature = "tokio"))]
+use async_timer::oneshot::{Oneshot, Timer};
+
+/// Interval used to tick at a fixed frequency.
+///
+/// The first tick completes after a full period, rather than immediately.
+pub(crate) struct Interval {
+    #[cfg(not(feature = "tokio"))]
+    inner: async_timer::Interval,
+    #[cfg(feature = "tokio")]
+    inner: tokio::time::Interval,
+}
+
+impl Interval {
+    /// Create a new `Interval` ticking every `period`.
+    #[cfg(not(feature = "tokio"))]
+    pub fn new(period: Duration) -> Self {
+        Self {
+            inner: async_timer::Interval::platform_new(period),
+        }
+    }
+
+    /// Create a new `Interval` ticking every `period`.
+    #[cfg(feature = "tokio")]
+    pub fn new(period: Duration) -> Self {
+        let start = tokio::time::Instant::now() + period;
+        Self {
+            inner: tokio::time::interval_at(start, period),
+        }
+    }
+
+    /// Poll for the next tick of this interval.
+    #[cfg(not(feature = "tokio"))]
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        Pin::new(&mut self.inner.as_mut()).poll(cx)
+    }
+
+    /// Poll for the next tick of this interval.
+    #[cfg(feature = "tokio")]
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_tick(cx).map(|_| ())
+    }
+
+    /// Wait for the next tick of this interval.
+    pub async fn tick(&mut self) {
+        future::poll_fn(|cx| self.poll_tick(cx)).await
+    }
+}
+
+/// Wait until a duration has elapsed.
+#[cfg(not(feature = "tokio"))]
+pub(crate) async fn sleep(duration: Duration) {
+    Timer::new(duration).await;
+}
+
+/// Wait until a duration has elapsed.
+#[cfg(feature = "tokio")]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
+
+/// Retrieve the current time, as used to expire entries.
+#[cfg(not(feature = "tokio"))]
+pub(crate) fn now() -> Instant {
+    Instant::now()
+}
+
+/// Retrieve the current time, as used to expire entries.
+#[cfg(feature = "tokio")]
+pub(crate) fn now() -> Instant {
+    tokio::time::Instant::now().into_std()
+}
@@ -1,5 +1,12 @@
 use retainer::*;
 
+use std::future;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
 #[tokio::test]
 async fn test_cache_size_operations() {
     let cache = Cache::<u8, u8>::new();
@@ -35,3 +42,2640 @@ async fn test_cache_update_operations() {
 
     assert_eq!(cache.get(&1).await.unwrap().value(), &5);
 }
+
+#[tokio::test]
+async fn test_cache_contains_key_operations() {
+    let cache = Cache::<String, u8>::new();
+
+    cache
+        .insert("one".to_owned(), 1, CacheExpiration::none())
+        .await;
+    cache.insert("two".to_owned(), 2, Instant::now()).await;
+
+    assert!(cache.contains_key("one").await);
+    assert!(!cache.contains_key("two").await);
+    assert!(!cache.contains_key("three").await);
+
+    // the expired entry is still stored until evicted
+    assert_eq!(cache.len().await, 2);
+}
+
+#[tokio::test]
+async fn test_cache_get_or_insert_with_operations() {
+    let cache = Cache::<u8, u8>::new();
+    let mut calls = 0;
+
+    let value = cache
+        .get_or_insert_with(1, CacheExpiration::none(), || {
+            calls += 1;
+            async { 1 }
+        })
+        .await;
+
+    assert_eq!(value.value(), &1);
+
+    let value = cache
+        .get_or_insert_with(1, CacheExpiration::none(), || {
+            calls += 1;
+            async { 2 }
+        })
+        .await;
+
+    assert_eq!(value.value(), &1);
+    assert_eq!(calls, 1);
+
+    cache.insert(2, 2, Instant::now()).await;
+
+    let value = cache
+        .get_or_insert_with(2, CacheExpiration::none(), || async { 3 })
+        .await;
+
+    assert_eq!(value.value(), &3);
+    assert_eq!(cache.len().await, 2);
+}
+
+#[tokio::test]
+async fn test_cache_guard_remaining() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Duration::from_secs(60)).await;
+
+    assert!(cache.get(&1).await.unwrap().remaining().is_none());
+
+    let remaining = cache.get(&2).await.unwrap().remaining().unwrap();
+
+    assert!(remaining > Duration::from_secs(59));
+    assert!(remaining <= Duration::from_secs(60));
+}
+
+#[tokio::test]
+async fn test_cache_get_or_insert_with_concurrency() {
+    let cache = Arc::new(Cache::<u8, usize>::new());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut handles = Vec::new();
+
+    for _ in 0..10 {
+        let cache = cache.clone();
+        let calls = calls.clone();
+
+        handles.push(tokio::spawn(async move {
+            *cache
+                .get_or_insert_with(1, CacheExpiration::none(), || {
+                    future::ready(calls.fetch_add(1, Ordering::SeqCst))
+                })
+                .await
+        }));
+    }
+
+    for handle in handles {
+        assert_eq!(handle.await.unwrap(), 0);
+    }
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_cache_get_or_try_insert_with_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    let result = cache
+        .get_or_try_insert_with(1, CacheExpiration::none(), || async { Err("failed") })
+        .await;
+
+    assert_eq!(result.err(), Some("failed"));
+    assert!(cache.is_empty().await);
+
+    let result = cache
+        .get_or_try_insert_with(1, CacheExpiration::none(), || async { Ok::<_, &str>(1) })
+        .await;
+
+    assert_eq!(result.unwrap().value(), &1);
+
+    let result = cache
+        .get_or_try_insert_with(1, CacheExpiration::none(), || async { Err("not called") })
+        .await;
+
+    assert_eq!(result.unwrap().value(), &1);
+}
+
+#[tokio::test]
+async fn test_cache_get_or_try_insert_with_slow_loader() {
+    let cache = Arc::new(Cache::<u8, u8>::new());
+    let clone = cache.clone();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+
+    let loader = tokio::spawn(async move {
+        *clone
+            .get_or_try_insert_with(2, CacheExpiration::none(), || async {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                Ok::<_, ()>(2)
+            })
+            .await
+            .unwrap()
+    });
+
+    // give the loader time to start running
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // readers and writers should not be blocked by the loader
+    let read = tokio::time::timeout(Duration::from_millis(100), cache.get(&1)).await;
+    assert_eq!(read.unwrap().unwrap().value(), &1);
+
+    let write = cache.insert(2, 3, CacheExpiration::none());
+    assert!(tokio::time::timeout(Duration::from_millis(100), write)
+        .await
+        .is_ok());
+
+    // the value inserted during the load should be preferred
+    assert_eq!(loader.await.unwrap(), 3);
+    assert_eq!(cache.get(&2).await.unwrap().value(), &3);
+}
+
+#[tokio::test]
+async fn test_cache_get_or_try_insert_with_coalescing() {
+    let cache = Arc::new(Cache::<u8, usize>::new());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut handles = Vec::new();
+
+    for _ in 0..50 {
+        let cache = cache.clone();
+        let calls = calls.clone();
+
+        handles.push(tokio::spawn(async move {
+            cache
+                .get_or_try_insert_with(1, CacheExpiration::none(), || async move {
+                    let call = calls.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+
+                    // the first load fails, so a waiting caller must retry
+                    if call == 0 {
+                        return Err(());
+                    }
+
+                    Ok(call)
+                })
+                .await
+                .map(|guard| *guard)
+        }));
+    }
+
+    let mut failures = 0;
+
+    for handle in handles {
+        match handle.await.unwrap() {
+            Ok(value) => assert_eq!(value, 1),
+            Err(()) => failures += 1,
+        }
+    }
+
+    assert_eq!(failures, 1);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_cache_get_or_load_single_flight() {
+    let cache = Arc::new(Cache::<u8, usize>::new());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut handles = Vec::new();
+
+    for _ in 0..50 {
+        let cache = cache.clone();
+        let calls = calls.clone();
+
+        handles.push(tokio::spawn(async move {
+            *cache
+                .get_or_load(1, CacheExpiration::none(), || async move {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    calls.fetch_add(1, Ordering::SeqCst) + 1
+                })
+                .await
+        }));
+    }
+
+    for handle in handles {
+        assert_eq!(handle.await.unwrap(), 1);
+    }
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_cache_get_or_load_cancelled_waiters() {
+    use futures_lite::future::{block_on, poll_once};
+
+    let cache = Arc::new(Cache::<u8, u8>::new());
+
+    for _ in 0..5000 {
+        let barrier = Arc::new(Barrier::new(8));
+        let mut threads = Vec::new();
+
+        for _ in 0..8 {
+            let cache = cache.clone();
+            let barrier = barrier.clone();
+
+            threads.push(thread::spawn(move || {
+                let mut load =
+                    Box::pin(cache.get_or_load(1, CacheExpiration::none(), future::pending::<u8>));
+
+                // join the load, and then cancel every caller at the same time
+                assert!(block_on(poll_once(&mut load)).is_none());
+                barrier.wait();
+                drop(load);
+            }));
+        }
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(cache.loading(), 0);
+    }
+}
+
+#[tokio::test]
+async fn test_cache_get_or_try_insert_with_panicking_loader() {
+    let cache = Arc::new(Cache::<u8, u8>::new());
+    let clone = cache.clone();
+
+    let panicking = tokio::spawn(async move {
+        let _ = clone
+            .get_or_try_insert_with(1, CacheExpiration::none(), || async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                panic!("loader failed");
+                #[allow(unreachable_code)]
+                Ok::<_, ()>(0)
+            })
+            .await;
+    });
+
+    // give the panicking loader time to start running
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    let waiting =
+        cache.get_or_try_insert_with(1, CacheExpiration::none(), || async { Ok::<_, ()>(1) });
+
+    let result = tokio::time::timeout(Duration::from_secs(1), waiting).await;
+
+    assert!(panicking.await.is_err());
+    assert_eq!(result.unwrap().unwrap().value(), &1);
+}
+
+#[tokio::test]
+async fn test_cache_capacity_operations() {
+    let cache = Cache::<u8, u8>::new().with_max_capacity(2);
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+
+    // access the first key so that the second is least recently used
+    assert!(cache.get(&1).await.is_some());
+
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    assert_eq!(cache.len().await, 2);
+    assert!(cache.contains_key(&1).await);
+    assert!(!cache.contains_key(&2).await);
+    assert!(cache.contains_key(&3).await);
+
+    // replacing an existing key should not evict anything
+    cache.insert(3, 4, CacheExpiration::none()).await;
+
+    assert_eq!(cache.len().await, 2);
+    assert!(cache.contains_key(&1).await);
+
+    // expired entries should be evicted before any others
+    cache.insert(4, 4, Instant::now()).await;
+    cache.insert(5, 5, CacheExpiration::none()).await;
+
+    assert_eq!(cache.len().await, 2);
+    assert!(cache.contains_key(&3).await);
+    assert!(cache.contains_key(&5).await);
+}
+
+#[tokio::test]
+async fn test_cache_capacity_ignores_deadlines() {
+    let cache = Cache::<u8, u8>::new().with_max_capacity(2);
+
+    cache.insert(1, 1, Duration::from_secs(60)).await;
+    cache.insert(2, 2, Duration::from_secs(120)).await;
+
+    // the entry expiring soonest is kept, as it was recently used
+    assert!(cache.get(&1).await.is_some());
+    cache.insert(3, 3, Duration::from_secs(180)).await;
+
+    assert_eq!(cache.keys().await, vec![1, 3]);
+}
+
+#[tokio::test]
+async fn test_cache_max_entries_victim_selection() {
+    let cache = Cache::<u8, u8>::new().with_max_entries(3);
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Duration::from_secs(120)).await;
+    cache.insert(3, 3, Duration::from_secs(60)).await;
+
+    // the entry expiring soonest is evicted, even if recently used
+    assert!(cache.get(&3).await.is_some());
+    cache.insert(4, 4, Duration::from_secs(180)).await;
+
+    assert_eq!(cache.keys().await, vec![1, 2, 4]);
+
+    // entries without an expiration are evicted last
+    cache.insert(5, 5, CacheExpiration::none()).await;
+    cache.insert(6, 6, CacheExpiration::none()).await;
+
+    assert_eq!(cache.keys().await, vec![1, 5, 6]);
+
+    // and then the least recently used entry is evicted
+    assert!(cache.get(&1).await.is_some());
+    cache.insert(7, 7, CacheExpiration::none()).await;
+
+    assert_eq!(cache.keys().await, vec![1, 6, 7]);
+}
+
+#[tokio::test]
+async fn test_cache_unbounded_capacity() {
+    let cache = Cache::<u8, u8>::new().with_max_capacity(0);
+
+    for i in 0..100 {
+        cache.insert(i, i, CacheExpiration::none()).await;
+    }
+
+    assert_eq!(cache.len().await, 100);
+}
+
+#[tokio::test]
+async fn test_cache_sliding_expiration() {
+    let cache = Cache::<u8, u8>::new().with_sliding_expiration();
+
+    cache.insert(1, 1, Duration::from_millis(200)).await;
+    cache.insert(2, 2, Duration::from_millis(200)).await;
+    cache.insert_untracked(3, 3).await;
+
+    // keep reading the first key to keep it alive
+    for _ in 0..4 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(cache.get(&1).await.is_some());
+    }
+
+    assert!(cache.get(&2).await.is_none());
+    assert!(cache.get(&3).await.unwrap().remaining().is_none());
+}
+
+#[tokio::test]
+async fn test_cache_stats_operations() {
+    let cache = Cache::<u8, u8>::new().with_max_capacity(2);
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    assert!(cache.get(&1).await.is_some());
+    assert!(cache.get(&2).await.is_none());
+    assert!(cache.get(&3).await.is_none());
+
+    let stats = cache.stats();
+
+    assert_eq!(stats.hits(), 1);
+    assert_eq!(stats.misses(), 2);
+    assert_eq!(stats.evictions(), 0);
+    assert!((stats.hit_ratio() - 1.0 / 3.0).abs() < f64::EPSILON);
+
+    // purging should count the expired entry as evicted
+    cache.purge(2, 0.25).await;
+
+    assert_eq!(cache.stats().evictions(), 1);
+
+    // as should any entries evicted due to capacity
+    cache.insert(3, 3, CacheExpiration::none()).await;
+    cache.insert(4, 4, CacheExpiration::none()).await;
+
+    assert_eq!(cache.stats().evictions(), 2);
+
+    cache.reset_stats();
+
+    assert_eq!(cache.stats(), CacheStats::default());
+    assert_eq!(cache.stats().hit_ratio(), 0.0);
+}
+
+#[tokio::test]
+async fn test_cache_remove_entry_operations() {
+    let cache = Cache::<u8, u8>::new();
+    let expiration = Instant::now() + Duration::from_secs(60);
+
+    cache.insert(1, 1, expiration).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    let (key, value, removed) = cache.remove_entry(&1).await.unwrap();
+
+    assert_eq!(key, 1);
+    assert_eq!(value, 1);
+    assert_eq!(removed.instant(), &Some(expiration));
+    assert!(cache.remove_entry(&1).await.is_none());
+    assert!(cache.remove_entry(&2).await.is_none());
+
+    // re-inserting should preserve the original expiration
+    cache.insert(key, value, removed).await;
+
+    assert_eq!(
+        cache.get(&1).await.unwrap().expiration().instant(),
+        &Some(expiration)
+    );
+}
+
+#[tokio::test]
+async fn test_cache_get_mut_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    let value = {
+        let mut guard = cache.get_mut(&1).await.unwrap();
+        *guard += 1;
+        *guard
+    };
+
+    assert_eq!(value, 2);
+    assert_eq!(cache.get(&1).await.unwrap().value(), &2);
+    assert!(cache.get_mut(&2).await.is_none());
+    assert!(cache.get_mut(&3).await.is_none());
+}
+
+#[tokio::test]
+async fn test_cache_get_mut_blocks_readers() {
+    let cache = Arc::new(Cache::<u8, u8>::new());
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+
+    let mut guard = cache.get_mut(&1).await.unwrap();
+
+    let clone = cache.clone();
+    let reader = tokio::spawn(async move { *clone.get(&1).await.unwrap() });
+
+    // the reader cannot complete whilst the write guard is held
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!reader.is_finished());
+
+    *guard += 1;
+    drop(guard);
+
+    assert_eq!(reader.await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn test_cache_ttl_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Duration::from_secs(60)).await;
+    cache.insert(3, 3, Instant::now()).await;
+
+    assert_eq!(cache.ttl(&1).await, Some(None));
+    assert!(cache.ttl(&2).await.unwrap().unwrap() > Duration::from_secs(59));
+    assert_eq!(cache.ttl(&3).await, None);
+    assert_eq!(cache.ttl(&4).await, None);
+}
+
+#[tokio::test]
+async fn test_cache_extend_expiration_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, Duration::from_secs(30)).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+    cache.insert(3, 3, Instant::now()).await;
+
+    let remaining = cache
+        .extend_expiration(&1, Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    assert!(remaining > Duration::from_secs(59));
+    assert!(remaining <= Duration::from_secs(60));
+
+    let remaining = cache
+        .extend_expiration(&2, Duration::from_secs(30))
+        .await
+        .unwrap();
+
+    assert!(remaining > Duration::from_secs(29));
+    assert!(remaining <= Duration::from_secs(30));
+
+    assert!(cache
+        .extend_expiration(&3, Duration::from_secs(30))
+        .await
+        .is_none());
+    assert!(cache.get(&3).await.is_none());
+}
+
+#[tokio::test]
+async fn test_cache_persist_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, Duration::from_secs(60)).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+    cache.insert(3, 3, Instant::now()).await;
+
+    assert!(cache.persist(&1).await);
+    assert_eq!(cache.ttl(&1).await, Some(None));
+
+    assert!(cache.persist(&2).await);
+    assert_eq!(cache.ttl(&2).await, Some(None));
+
+    assert!(!cache.persist(&3).await);
+    assert!(!cache.persist(&4).await);
+}
+
+#[tokio::test]
+async fn test_cache_eviction_listener() {
+    let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let clone = evicted.clone();
+
+    let cache = Cache::<u8, u8>::new()
+        .with_max_capacity(3)
+        .with_eviction_listener(move |k, v, cause| {
+            clone.lock().unwrap().push((*k, *v, cause));
+        });
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(1, 2, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    assert_eq!(cache.remove(&3).await, Some(3));
+
+    cache.purge(3, 0.25).await;
+
+    cache.insert(4, 4, CacheExpiration::none()).await;
+    cache.insert(5, 5, CacheExpiration::none()).await;
+    cache.insert(6, 6, CacheExpiration::none()).await;
+
+    assert_eq!(
+        *evicted.lock().unwrap(),
+        vec![
+            (1, 1, EvictionCause::Replaced),
+            (3, 3, EvictionCause::Manual),
+            (2, 2, EvictionCause::Expired),
+            (1, 2, EvictionCause::Capacity),
+        ]
+    );
+
+    evicted.lock().unwrap().clear();
+    cache.clear().await;
+
+    assert_eq!(
+        *evicted.lock().unwrap(),
+        vec![
+            (4, 4, EvictionCause::Cleared),
+            (5, 5, EvictionCause::Cleared),
+            (6, 6, EvictionCause::Cleared),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_cache_eviction_listener_owned() {
+    let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let clone = evicted.clone();
+
+    let cache = Cache::<u8, String>::new()
+        .with_max_capacity(3)
+        .with_eviction_listener_owned(move |k, v, cause| {
+            clone.lock().unwrap().push((k, v, cause));
+        });
+
+    cache
+        .insert(1, "1".to_owned(), CacheExpiration::none())
+        .await;
+    cache.insert(2, "2".to_owned(), Instant::now()).await;
+    cache
+        .insert(3, "3".to_owned(), CacheExpiration::none())
+        .await;
+
+    // values handed back to the caller are not passed to the listener
+    assert_eq!(cache.remove(&3).await, Some("3".to_owned()));
+
+    cache.purge(3, 0.25).await;
+
+    cache
+        .insert(4, "4".to_owned(), CacheExpiration::none())
+        .await;
+    cache
+        .insert(5, "5".to_owned(), CacheExpiration::none())
+        .await;
+    cache
+        .insert(6, "6".to_owned(), CacheExpiration::none())
+        .await;
+
+    cache.retain(|k, _| *k != 4).await;
+    cache.clear().await;
+
+    assert_eq!(
+        *evicted.lock().unwrap(),
+        vec![
+            (2, "2".to_owned(), EvictionCause::Expired),
+            (1, "1".to_owned(), EvictionCause::Capacity),
+            (4, "4".to_owned(), EvictionCause::Manual),
+            (5, "5".to_owned(), EvictionCause::Cleared),
+            (6, "6".to_owned(), EvictionCause::Cleared),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_cache_eviction_listener_owned_removals() {
+    let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let clone = evicted.clone();
+
+    let cache = Cache::<u8, u8>::new().with_eviction_listener_owned(move |k, v, cause| {
+        clone.lock().unwrap().push((k, v, cause));
+    });
+
+    for i in 1..=4 {
+        cache.insert(i, i, CacheExpiration::none()).await;
+    }
+
+    // explicitly removed values are handed back, rather than to the listener
+    assert_eq!(cache.remove(&1).await, Some(1));
+    assert_eq!(cache.remove_if(&2, |_| true).await, Some(Some(2)));
+    assert_eq!(cache.remove_many([&3]).await, vec![Some(3)]);
+    assert_eq!(cache.pop_first().await, Some((4, 4)));
+
+    assert!(evicted.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_cache_eviction_listener_owned_replacements() {
+    let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let clone = evicted.clone();
+
+    let cache = Cache::<u8, u8>::new().with_eviction_listener_owned(move |k, v, cause| {
+        clone.lock().unwrap().push((k, v, cause));
+    });
+
+    // replaced values are handed back, rather than to the listener
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    assert_eq!(cache.insert(1, 2, CacheExpiration::none()).await, Some(1));
+
+    assert!(evicted.lock().unwrap().is_empty());
+
+    // unless they had already expired, and so can't be handed back
+    cache.insert(2, 2, Instant::now()).await;
+    assert_eq!(cache.insert(2, 3, CacheExpiration::none()).await, None);
+
+    assert_eq!(
+        *evicted.lock().unwrap(),
+        vec![(2, 2, EvictionCause::Expired)]
+    );
+}
+
+#[cfg(feature = "async-channel")]
+#[tokio::test]
+async fn test_cache_eviction_listener_owned_expired_channel() {
+    let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let clone = evicted.clone();
+
+    let cache = Cache::<u8, u8>::new()
+        .with_max_capacity(2)
+        .with_expired_channel(4)
+        .with_eviction_listener_owned(move |k, v, cause| {
+            clone.lock().unwrap().push((k, v, cause));
+        });
+
+    let receiver = cache.expired_receiver().unwrap();
+
+    cache.insert(1, 1, Instant::now()).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+
+    // expired entries are published to the channel, rather than to the listener
+    cache.purge(2, 1.0).await;
+
+    assert_eq!(receiver.try_recv().unwrap(), (1, 1));
+    assert!(evicted.lock().unwrap().is_empty());
+
+    // but unexpired entries are still passed to the listener
+    cache.insert(3, 3, CacheExpiration::none()).await;
+    cache.insert(4, 4, CacheExpiration::none()).await;
+
+    assert!(receiver.try_recv().is_err());
+    assert_eq!(
+        *evicted.lock().unwrap(),
+        vec![(2, 2, EvictionCause::Capacity)]
+    );
+}
+
+#[tokio::test]
+async fn test_cache_borrowed_key_operations() {
+    let cache = Cache::<String, u8>::new();
+
+    cache.insert("one".to_owned(), 1, Instant::now()).await;
+    cache
+        .insert("two".to_owned(), 2, CacheExpiration::none())
+        .await;
+
+    // expired entries should not be resurrected
+    cache.set_expiration("one", CacheExpiration::none()).await;
+    assert!(cache.get("one").await.is_none());
+
+    cache.set_expiration("two", Duration::from_secs(60)).await;
+    assert!(cache.ttl("two").await.unwrap().is_some());
+
+    cache.update("two", |value| *value += 1).await;
+    assert_eq!(cache.get("two").await.unwrap().value(), &3);
+
+    assert_eq!(cache.remove("two").await, Some(3));
+    assert!(!cache.contains_key("two").await);
+}
+
+#[tokio::test]
+async fn test_cache_purge_full_sample() {
+    let cache = Cache::<u16, u16>::new();
+
+    for i in 0..1000 {
+        if i % 2 == 0 {
+            cache.insert(i, i, Instant::now()).await;
+        } else {
+            cache.insert(i, i, CacheExpiration::none()).await;
+        }
+    }
+
+    // a sample covering every entry should check each entry exactly once
+    cache.purge(1000, 1.0).await;
+
+    assert_eq!(cache.len().await, 500);
+    assert_eq!(cache.expired().await, 0);
+    assert_eq!(cache.stats().evictions(), 500);
+
+    // and samples larger than the cache should be clamped
+    cache.insert(1000, 1000, Instant::now()).await;
+    cache.purge(5000, 1.0).await;
+
+    assert_eq!(cache.len().await, 500);
+    assert_eq!(cache.expired().await, 0);
+}
+
+#[tokio::test]
+async fn test_cache_purge_partial_sample() {
+    let cache = Cache::<u16, u16>::new();
+
+    for i in 0..1000 {
+        cache.insert(i, i, Instant::now()).await;
+    }
+
+    // every sampled entry is expired, so each should map to a removal
+    cache.purge(10, 2.0).await;
+
+    assert_eq!(cache.len().await, 990);
+    assert_eq!(cache.stats().evictions(), 10);
+}
+
+#[tokio::test]
+async fn test_cache_set_expiration_operations() {
+    let cache = Cache::<u8, u8>::new();
+    let expiration = Instant::now() + Duration::from_secs(60);
+
+    cache.insert(1, 1, expiration).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    let previous = cache.set_expiration(&1, CacheExpiration::none()).await;
+
+    assert_eq!(previous.unwrap().instant(), &Some(expiration));
+    assert!(cache
+        .set_expiration(&1, Duration::from_secs(30))
+        .await
+        .unwrap()
+        .instant()
+        .is_none());
+
+    assert!(cache
+        .set_expiration(&2, CacheExpiration::none())
+        .await
+        .is_none());
+    assert!(cache
+        .set_expiration(&3, CacheExpiration::none())
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_cache_snapshot_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(3, 3, CacheExpiration::none()).await;
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    assert_eq!(cache.snapshot().await, vec![(1, 1), (3, 3)]);
+}
+
+#[tokio::test]
+async fn test_cache_update_results() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    let result = cache
+        .update(&1, |value| {
+            *value += 1;
+            *value
+        })
+        .await;
+
+    assert_eq!(result, Some(2));
+    assert_eq!(cache.update(&1, |_| ()).await, Some(()));
+    assert_eq!(cache.update(&2, |value| *value).await, None);
+    assert_eq!(cache.update(&3, |value| *value).await, None);
+}
+
+#[tokio::test]
+async fn test_cache_insert_if_absent_operations() {
+    let cache = Arc::new(Cache::<u8, u8>::new());
+
+    assert_eq!(
+        cache.insert_if_absent(1, 1, CacheExpiration::none()).await,
+        Ok(())
+    );
+    assert_eq!(
+        cache.insert_if_absent(1, 2, CacheExpiration::none()).await,
+        Err(2)
+    );
+    assert_eq!(cache.get(&1).await.unwrap().value(), &1);
+
+    // expired entries should be treated as absent
+    cache.insert(2, 2, Instant::now()).await;
+
+    assert_eq!(
+        cache.insert_if_absent(2, 3, CacheExpiration::none()).await,
+        Ok(())
+    );
+    assert_eq!(cache.get(&2).await.unwrap().value(), &3);
+
+    // try_insert is an alias of insert_if_absent
+    assert_eq!(
+        cache.try_insert(2, 4, CacheExpiration::none()).await,
+        Err(4)
+    );
+    assert_eq!(
+        cache.try_insert(4, 4, CacheExpiration::none()).await,
+        Ok(())
+    );
+
+    // only a single racing insertion should succeed
+    let mut handles = Vec::new();
+
+    for i in 0..10 {
+        let cache = cache.clone();
+        handles.push(tokio::spawn(async move {
+            cache
+                .insert_if_absent(3, i, CacheExpiration::none())
+                .await
+                .is_ok()
+        }));
+    }
+
+    let mut successes = 0;
+
+    for handle in handles {
+        if handle.await.unwrap() {
+            successes += 1;
+        }
+    }
+
+    assert_eq!(successes, 1);
+}
+
+#[tokio::test]
+async fn test_cache_update_or_insert_operations() {
+    let cache = Cache::<u8, u8>::new();
+    let expiration = Instant::now() + Duration::from_secs(60);
+
+    for _ in 0..3 {
+        cache
+            .update_or_insert(1, |value| *value += 1, || 1, expiration)
+            .await;
+    }
+
+    let guard = cache.get(&1).await.unwrap();
+
+    assert_eq!(guard.value(), &3);
+    assert_eq!(guard.expiration().instant(), &Some(expiration));
+
+    // expired entries should be replaced by the inserted value
+    cache.insert(2, 5, Instant::now()).await;
+    cache
+        .update_or_insert(2, |value| *value += 1, || 1, CacheExpiration::none())
+        .await;
+
+    assert_eq!(cache.get(&2).await.unwrap().value(), &1);
+}
+
+#[tokio::test]
+async fn test_cache_update_async_operations() {
+    let cache = Arc::new(Cache::<u8, u8>::new());
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+
+    assert!(
+        cache
+            .update_async(&1, |value| async move { value + 1 })
+            .await
+    );
+    assert!(
+        !cache
+            .update_async(&2, |value| async move { value + 1 })
+            .await
+    );
+    assert_eq!(cache.get(&1).await.unwrap().value(), &2);
+
+    // concurrent removal should discard the updated value
+    let clone = cache.clone();
+    let update = tokio::spawn(async move {
+        clone
+            .update_async(&1, |value| async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                value + 1
+            })
+            .await
+    });
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert_eq!(cache.remove(&1).await, Some(2));
+    assert!(!update.await.unwrap());
+    assert!(!cache.contains_key(&1).await);
+
+    // as should concurrent replacement
+    cache.insert(1, 1, CacheExpiration::none()).await;
+
+    let clone = cache.clone();
+    let update = tokio::spawn(async move {
+        clone
+            .update_async(&1, |value| async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                value + 1
+            })
+            .await
+    });
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    cache.insert(1, 5, CacheExpiration::none()).await;
+
+    assert!(!update.await.unwrap());
+    assert_eq!(cache.get(&1).await.unwrap().value(), &5);
+}
+
+#[tokio::test]
+async fn test_cache_weight_operations() {
+    let cache = Cache::<u8, String>::new()
+        .with_weigher(|_, value: &String| value.len())
+        .with_max_weight(10);
+
+    cache.insert_untracked(1, "aaaa".to_owned()).await;
+    cache.insert_untracked(2, "bbbb".to_owned()).await;
+
+    assert_eq!(cache.weight(), 8);
+
+    // touch the first key to make the second the least recently used
+    cache.get(&1).await.unwrap();
+
+    // inserting a third entry should evict the second
+    cache.insert_untracked(3, "cccc".to_owned()).await;
+
+    assert_eq!(cache.weight(), 8);
+    assert!(cache.contains_key(&1).await);
+    assert!(!cache.contains_key(&2).await);
+    assert!(cache.contains_key(&3).await);
+
+    // updates should re-calculate the weight
+    cache.update(&1, |value| value.push('a')).await;
+    assert_eq!(cache.weight(), 9);
+
+    // replacing should only count the new weight
+    cache.insert_untracked(3, "c".to_owned()).await;
+    assert_eq!(cache.weight(), 6);
+
+    // removals should release weight
+    cache.remove(&1).await;
+    assert_eq!(cache.weight(), 1);
+
+    cache.clear().await;
+    assert_eq!(cache.weight(), 0);
+}
+
+#[tokio::test]
+async fn test_cache_weight_without_weigher() {
+    let cache = Cache::<u8, u8>::new().with_max_weight(2);
+
+    cache.insert_untracked(1, 1).await;
+    cache.insert_untracked(2, 2).await;
+    cache.insert_untracked(3, 3).await;
+
+    assert_eq!(cache.len().await, 2);
+    assert_eq!(cache.weight(), 2);
+    assert!(!cache.contains_key(&1).await);
+}
+
+#[tokio::test]
+async fn test_cache_try_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    assert_eq!(cache.insert_nowait(1, 1, CacheExpiration::none()), Ok(None));
+    assert_eq!(
+        cache.insert_nowait(1, 2, CacheExpiration::none()),
+        Ok(Some(1))
+    );
+    assert_eq!(cache.insert_nowait(2, 2, Instant::now()), Ok(None));
+
+    assert_eq!(cache.try_get(&1).unwrap().value(), &2);
+    assert!(cache.try_get(&2).is_none());
+    assert!(cache.try_get(&3).is_none());
+
+    // hold the write lock to simulate contention
+    let guard = cache.get_mut(&1).await.unwrap();
+
+    assert!(cache.try_get(&1).is_none());
+    assert_eq!(
+        cache.insert_nowait(3, 3, CacheExpiration::none()),
+        Err((3, 3))
+    );
+
+    drop(guard);
+
+    assert_eq!(cache.try_get(&1).unwrap().value(), &2);
+    assert!(cache.try_get(&3).is_none());
+}
+
+#[tokio::test]
+async fn test_cache_insert_many_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 0, CacheExpiration::none()).await;
+
+    let inserted = cache
+        .insert_many((1..=3).map(|i| (i, i, CacheExpiration::none())))
+        .await;
+
+    assert_eq!(inserted, 3);
+    assert_eq!(cache.len().await, 3);
+
+    for i in 1..=3 {
+        assert_eq!(cache.get(&i).await.unwrap().value(), &i);
+    }
+}
+
+#[tokio::test]
+async fn test_cache_peek_operations() {
+    let cache = Cache::<u8, u8>::new().with_max_capacity(2);
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    assert!(cache.peek(&2).await.is_none());
+
+    cache.insert(2, 2, CacheExpiration::none()).await;
+
+    // peeking should not mark the first key as recently used
+    assert_eq!(cache.peek(&1).await.unwrap().value(), &1);
+    assert_eq!(cache.stats().hits(), 0);
+
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    assert!(cache.peek(&1).await.is_none());
+    assert!(cache.peek(&2).await.is_some());
+}
+
+#[tokio::test]
+async fn test_cache_peek_with_sliding_expiration() {
+    let cache = Cache::<u8, u8>::new().with_sliding_expiration();
+
+    cache.insert(1, 1, Duration::from_millis(100)).await;
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(cache.peek(&1).await.is_some());
+
+    tokio::time::sleep(Duration::from_millis(75)).await;
+    assert!(cache.peek(&1).await.is_none());
+}
+
+#[tokio::test]
+async fn test_cache_system_time_expiration() {
+    let future = SystemTime::now() + Duration::from_secs(60);
+    let expiration = CacheExpiration::from(future);
+
+    assert!(!expiration.is_expired());
+    assert!(expiration.remaining().unwrap() > Duration::from_secs(59));
+    assert!(expiration.duration().is_none());
+
+    let past = SystemTime::now() - Duration::from_secs(60);
+    let expiration = CacheExpiration::from(past);
+
+    assert!(expiration.is_expired());
+    assert_eq!(expiration.remaining(), Some(Duration::from_secs(0)));
+
+    // unrepresentable times never expire, rather than panicking
+    assert!(CacheExpiration::from(Duration::MAX).instant().is_none());
+    assert_eq!(
+        CacheExpiration::from(Duration::MAX).duration(),
+        &Some(Duration::MAX)
+    );
+
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, future).await;
+    cache.insert(2, 2, past).await;
+
+    assert!(cache.contains_key(&1).await);
+    assert!(!cache.contains_key(&2).await);
+}
+
+#[cfg(feature = "chrono")]
+#[tokio::test]
+async fn test_cache_chrono_expiration() {
+    let now = chrono::DateTime::<chrono::Utc>::from(SystemTime::now());
+
+    let future = now + chrono::Duration::seconds(60);
+    let past = now - chrono::Duration::seconds(60);
+
+    assert!(!CacheExpiration::from(future).is_expired());
+    assert!(CacheExpiration::from(past).is_expired());
+}
+
+#[tokio::test]
+async fn test_cache_compare_and_swap_operations() {
+    let cache = Cache::<u8, u8>::new();
+    let expiration = Instant::now() + Duration::from_secs(60);
+
+    cache.insert(1, 1, expiration).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    // matching values should be swapped, preserving expiration
+    assert_eq!(cache.compare_and_swap(&1, &1, 5).await, Ok(()));
+
+    let value = cache.get(&1).await.unwrap();
+
+    assert_eq!(value.value(), &5);
+    assert_eq!(value.expiration().instant(), &Some(expiration));
+
+    // mismatched values should return the current value
+    assert_eq!(cache.compare_and_swap(&1, &1, 6).await, Err(Some(5)));
+    assert_eq!(cache.get(&1).await.unwrap().value(), &5);
+
+    // missing and expired entries should be reported as absent
+    assert_eq!(cache.compare_and_swap(&2, &2, 6).await, Err(None));
+    assert_eq!(cache.compare_and_swap(&3, &3, 6).await, Err(None));
+}
+
+#[tokio::test]
+async fn test_cache_monitor_with_shutdown() {
+    let cache = Arc::new(Cache::<u8, u8>::new());
+    let clone = cache.clone();
+
+    cache.insert(1, 1, Duration::from_millis(50)).await;
+
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+    let monitor = tokio::spawn(async move {
+        clone
+            .monitor_with_shutdown(4, 0.25, Duration::from_millis(25), async {
+                rx.await.ok();
+            })
+            .await
+    });
+
+    // wait until the monitor has purged the expired entry
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(cache.len().await, 0);
+
+    tx.send(()).unwrap();
+
+    tokio::time::timeout(Duration::from_secs(1), monitor)
+        .await
+        .expect("monitor should shutdown")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_cache_replace_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    assert_eq!(cache.replace(&1, 5, Duration::from_secs(60)).await, Some(1));
+    assert_eq!(cache.replace(&2, 5, CacheExpiration::none()).await, None);
+    assert_eq!(cache.replace(&3, 5, CacheExpiration::none()).await, None);
+
+    // the expiration should also be replaced
+    let value = cache.get(&1).await.unwrap();
+
+    assert_eq!(value.value(), &5);
+    assert!(value.remaining().is_some());
+
+    // missing keys should not be inserted
+    assert!(!cache.contains_key(&2).await);
+    assert!(!cache.contains_key(&3).await);
+}
+
+#[tokio::test]
+async fn test_cache_keys_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(3, 3, CacheExpiration::none()).await;
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    assert_eq!(cache.keys().await, vec![1, 3]);
+    assert_eq!(cache.keys_unfiltered().await, vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn test_cache_retain_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    for i in 1..=6 {
+        cache.insert(i, i, CacheExpiration::none()).await;
+    }
+
+    cache.insert(7, 8, Instant::now()).await;
+
+    // removes odd values, and the expired entry
+    assert_eq!(cache.retain(|_, value| value % 2 == 0).await, 4);
+    assert_eq!(cache.keys_unfiltered().await, vec![2, 4, 6]);
+}
+
+#[tokio::test]
+async fn test_cache_cloned_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(2, 4, CacheExpiration::none()).await;
+    cache.insert(1, 2, CacheExpiration::none()).await;
+    cache.insert(3, 6, Instant::now()).await;
+
+    assert_eq!(cache.values_cloned().await, vec![2, 4]);
+    assert_eq!(cache.pairs_cloned().await, vec![(1, 2), (2, 4)]);
+}
+
+#[tokio::test]
+async fn test_cache_entries_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Duration::from_secs(60)).await;
+    cache.insert(3, 3, Instant::now()).await;
+
+    let entries = cache.entries().await;
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0], (1, 1, None));
+    assert_eq!((entries[1].0, entries[1].1), (2, 2));
+    assert!(entries[1].2.unwrap() > Duration::from_secs(59));
+}
+
+#[tokio::test]
+async fn test_cache_stream_operations() {
+    use futures_lite::StreamExt;
+
+    let cache = Cache::<u16, u16>::new();
+
+    for i in 0..200 {
+        if i % 4 == 0 {
+            cache.insert(i, i, Instant::now()).await;
+        } else {
+            cache.insert(i, i, CacheExpiration::none()).await;
+        }
+    }
+
+    let pairs: Vec<_> = cache.stream().collect().await;
+
+    assert_eq!(pairs.len(), 150);
+    assert_eq!(pairs, cache.pairs_cloned().await);
+
+    // the lock should not be held between items
+    let mut stream = Box::pin(cache.stream());
+
+    assert_eq!(stream.next().await, Some((1, 1)));
+    assert!(cache.remove(&199).await.is_some());
+    assert_eq!(stream.count().await, 148);
+}
+
+#[tokio::test]
+async fn test_cache_remove_if_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    assert_eq!(cache.remove_if(&1, |value| *value == 2).await, Some(None));
+    assert!(cache.contains_key(&1).await);
+
+    assert_eq!(
+        cache.remove_if(&1, |value| *value == 1).await,
+        Some(Some(1))
+    );
+    assert!(!cache.contains_key(&1).await);
+
+    assert_eq!(cache.remove_if(&2, |_| true).await, None);
+    assert_eq!(cache.remove_if(&3, |_| true).await, None);
+}
+
+#[tokio::test]
+async fn test_cache_tag_operations() {
+    let cache = Cache::<u8, u8>::new();
+    let users = vec!["users".to_owned()];
+    let both = vec!["users".to_owned(), "orders".to_owned()];
+
+    cache
+        .insert_tagged(1, 1, CacheExpiration::none(), users.clone())
+        .await;
+    cache
+        .insert_tagged(2, 2, CacheExpiration::none(), both.clone())
+        .await;
+    cache
+        .insert_tagged(3, 3, CacheExpiration::none(), users.clone())
+        .await;
+    cache.insert(4, 4, CacheExpiration::none()).await;
+
+    // replacing should carry tags, but inserting should not
+    cache.replace(&1, 5, CacheExpiration::none()).await;
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    assert_eq!(cache.invalidate_tag("orders").await, 1);
+    assert_eq!(cache.keys().await, vec![1, 3, 4]);
+
+    assert_eq!(cache.invalidate_tag("users").await, 1);
+    assert_eq!(cache.keys().await, vec![3, 4]);
+
+    assert_eq!(cache.invalidate_tag("missing").await, 0);
+}
+
+#[tokio::test]
+async fn test_cache_drain_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    assert_eq!(cache.drain().await, vec![(1, 1), (3, 3)]);
+    assert!(cache.is_empty().await);
+    assert_eq!(cache.weight(), 0);
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    let drained = cache.drain_unfiltered().await;
+
+    assert_eq!(drained.len(), 2);
+    assert!(drained[1].2.is_expired());
+    assert!(cache.is_empty().await);
+}
+
+#[tokio::test]
+async fn test_cache_seeded_purge() {
+    async fn purged(seed: u64) -> Vec<u8> {
+        let cache = Cache::<u8, u8>::new().with_rng_seed(seed);
+
+        for i in 0..100 {
+            cache.insert(i, i, Instant::now()).await;
+        }
+
+        // a threshold above 1 means only a single batch is sampled
+        cache.purge(10, 2.0).await;
+        cache.keys_unfiltered().await
+    }
+
+    let remaining = purged(42).await;
+
+    assert_eq!(remaining.len(), 90);
+    assert_eq!(remaining, purged(42).await);
+    assert_ne!(remaining, purged(7).await);
+}
+
+#[tokio::test]
+async fn test_cache_drain_expired_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    for i in 0..10 {
+        if i % 2 == 0 {
+            cache.insert(i, i, Instant::now()).await;
+        } else {
+            cache.insert(i, i, CacheExpiration::none()).await;
+        }
+    }
+
+    let drained = cache.drain_expired().await;
+
+    assert_eq!(drained, vec![(0, 0), (2, 2), (4, 4), (6, 6), (8, 8)]);
+    assert_eq!(cache.len().await, 5);
+    assert_eq!(cache.expired().await, 0);
+    assert_eq!(cache.stats().evictions(), 5);
+}
+
+#[tokio::test]
+async fn test_cache_clear_expired_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, Instant::now()).await;
+    cache.insert(2, 2, Instant::now()).await;
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    assert_eq!(cache.clear_expired().await, 2);
+    assert_eq!(cache.clear_expired().await, 0);
+    assert_eq!(cache.keys_unfiltered().await, vec![3]);
+}
+
+#[tokio::test]
+async fn test_cache_expiration_copy_operations() {
+    let cache = Cache::<u8, u8>::new();
+    let sooner = CacheExpiration::from(Duration::from_secs(60));
+    let later = CacheExpiration::from(Duration::from_secs(120));
+
+    assert!(sooner < later);
+    assert!(CacheExpiration::none() < sooner);
+
+    // comparisons only consider the instant of an expiration
+    let instant = CacheExpiration::new(*sooner.instant().as_ref().unwrap());
+    let idle = sooner.with_idle(Duration::from_secs(1));
+
+    assert_eq!(sooner, instant);
+    assert_eq!(sooner, idle);
+    assert_eq!(sooner.cmp(&idle), std::cmp::Ordering::Equal);
+
+    cache.insert(1, 1, later).await;
+    cache.insert(2, 2, sooner).await;
+
+    // copy the deadline of the first entry onto the second
+    let expiration = *cache.get(&1).await.unwrap().expiration();
+    cache.set_expiration(&2, expiration).await;
+
+    assert_eq!(cache.get(&2).await.unwrap().expiration(), &later);
+}
+
+#[tokio::test]
+async fn test_cache_pop_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    for i in 1..=6 {
+        if i <= 2 || i >= 5 {
+            cache.insert(i, i, Instant::now()).await;
+        } else {
+            cache.insert(i, i, CacheExpiration::none()).await;
+        }
+    }
+
+    // expired entries should be skipped and removed
+    assert_eq!(cache.pop_first().await, Some((3, 3)));
+    assert_eq!(cache.pop_last().await, Some((4, 4)));
+
+    assert_eq!(cache.pop_first().await, None);
+    assert_eq!(cache.pop_last().await, None);
+
+    assert!(cache.is_empty().await);
+    assert_eq!(cache.stats().evictions(), 4);
+}
+
+#[tokio::test]
+async fn test_cache_insert_untracked_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, Instant::now()).await;
+
+    // expired entries should not be returned when displaced
+    assert_eq!(cache.insert_untracked(1, 2).await, None);
+    assert_eq!(cache.insert_untracked(1, 3).await, Some(2));
+    assert_eq!(cache.ttl(&1).await, Some(None));
+}
+
+#[tokio::test]
+async fn test_cache_get_range_operations() {
+    let cache = Cache::<(u8, u8), u8>::new();
+
+    for tenant in 1..=3 {
+        for id in 1..=3 {
+            if id == 2 {
+                cache.insert((tenant, id), id, Instant::now()).await;
+            } else {
+                cache
+                    .insert((tenant, id), id, CacheExpiration::none())
+                    .await;
+            }
+        }
+    }
+
+    assert_eq!(
+        cache.get_range((2, 0)..(3, 0)).await,
+        vec![((2, 1), 1), ((2, 3), 3)]
+    );
+    assert_eq!(cache.get_range(..).await.len(), 6);
+    assert!(cache.get_range((4, 0)..).await.is_empty());
+
+    // borrowed bounds should be supported for owned keys
+    let cache = Cache::<String, u8>::new();
+
+    for (idx, key) in ["a", "b", "c", "d"].iter().enumerate() {
+        cache
+            .insert(key.to_string(), idx as u8, CacheExpiration::none())
+            .await;
+    }
+
+    let range = (Bound::Included("b"), Bound::Excluded("d"));
+
+    assert_eq!(
+        cache.get_range::<str, _>(range).await,
+        vec![("b".to_owned(), 1), ("c".to_owned(), 2)]
+    );
+}
+
+#[tokio::test]
+async fn test_cache_remove_range_operations() {
+    let cache = Cache::<(u8, u8), u8>::new();
+
+    for tenant in 1..=3 {
+        for id in 1..=3 {
+            if id == 2 {
+                cache.insert((tenant, id), id, Instant::now()).await;
+            } else {
+                cache
+                    .insert((tenant, id), id, CacheExpiration::none())
+                    .await;
+            }
+        }
+    }
+
+    // expired entries are removed, but not counted
+    assert_eq!(cache.remove_range((2, 0)..(3, 0)).await, 2);
+    assert_eq!(cache.len().await, 6);
+
+    // empty ranges should remove nothing
+    assert_eq!(cache.remove_range((2, 0)..(2, 0)).await, 0);
+    assert_eq!(cache.remove_range((4, 0)..).await, 0);
+
+    // and full ranges should remove everything
+    assert_eq!(cache.remove_range(..).await, 4);
+    assert!(cache.is_empty().await);
+}
+
+#[tokio::test]
+async fn test_cache_prefix_operations() {
+    let cache = Cache::<String, u8>::new();
+
+    let keys = [
+        "user:1:name",
+        "user:1:profile",
+        "user:12:name",
+        "user:2:name",
+        "\u{10FFFF}",
+        "\u{10FFFF}\u{10FFFF}",
+    ];
+
+    for key in keys.iter() {
+        cache
+            .insert(key.to_string(), 1, CacheExpiration::none())
+            .await;
+    }
+
+    cache
+        .insert("user:1:avatar".to_owned(), 1, Instant::now())
+        .await;
+
+    assert_eq!(
+        cache.keys_with_prefix("user:1:").await,
+        vec!["user:1:name".to_owned(), "user:1:profile".to_owned()]
+    );
+
+    // expired entries are removed, but not counted
+    assert_eq!(cache.remove_prefix("user:1:").await, 2);
+    assert_eq!(cache.len().await, 4);
+
+    // prefixes at the very end of the key space should be handled
+    assert_eq!(cache.keys_with_prefix("\u{10FFFF}").await.len(), 2);
+    assert_eq!(cache.remove_prefix("\u{10FFFF}").await, 2);
+
+    assert_eq!(cache.remove_prefix("missing").await, 0);
+    assert_eq!(cache.remove_prefix("").await, 2);
+    assert!(cache.is_empty().await);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(start_paused = true)]
+async fn test_cache_idle_expiration() {
+    let cache = Cache::<u8, u8>::new();
+    let idle = Duration::from_millis(100);
+
+    cache
+        .insert(1, 1, CacheExpiration::none().with_idle(idle))
+        .await;
+    cache
+        .insert(2, 2, CacheExpiration::none().with_idle(idle))
+        .await;
+    cache
+        .insert(3, 3, CacheExpiration::from(150).with_idle(idle))
+        .await;
+
+    // accessing entries should keep them alive
+    for _ in 0..2 {
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(cache.get(&1).await.is_some());
+        assert!(cache.get(&3).await.is_some());
+    }
+
+    tokio::time::sleep(Duration::from_millis(60)).await;
+
+    // the idle entry should be gone, as should the hard capped entry
+    assert!(cache.get(&1).await.is_some());
+    assert!(cache.get(&2).await.is_none());
+    assert!(cache.get(&3).await.is_none());
+
+    // idle entries should be evicted by a purge
+    tokio::time::sleep(Duration::from_millis(150)).await;
+
+    cache.purge(10, 0.25).await;
+    assert!(cache.is_empty().await);
+}
+
+#[tokio::test]
+async fn test_cache_high_water_mark() {
+    let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let clone = calls.clone();
+
+    let cache = Cache::<u8, u8>::new().with_high_water_mark(2, move |len| {
+        clone.lock().unwrap().push(len);
+    });
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+
+    assert!(calls.lock().unwrap().is_empty());
+
+    // only crossing the mark should notify
+    cache.insert(3, 3, CacheExpiration::none()).await;
+    cache.insert(4, 4, CacheExpiration::none()).await;
+    cache.insert(4, 5, CacheExpiration::none()).await;
+
+    assert_eq!(*calls.lock().unwrap(), vec![3]);
+
+    // dropping back below the mark should allow it to notify again
+    cache.remove(&3).await;
+    cache.remove(&4).await;
+    cache
+        .get_or_load(5, CacheExpiration::none(), || async { 5 })
+        .await;
+
+    assert_eq!(*calls.lock().unwrap(), vec![3, 3]);
+}
+
+#[tokio::test]
+async fn test_cache_insert_batch_operations() {
+    let cache = Cache::<u8, u8>::new();
+    let expiration = Instant::now() + Duration::from_secs(60);
+
+    let inserted = cache
+        .insert_batch((1..=3).map(|i| (i, i)), expiration)
+        .await;
+
+    assert_eq!(inserted, 3);
+
+    for i in 1..=3 {
+        let value = cache.get(&i).await.unwrap();
+
+        assert_eq!(value.value(), &i);
+        assert_eq!(value.expiration().instant(), &Some(expiration));
+    }
+}
+
+#[tokio::test]
+async fn test_cache_get_many_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    let keys: &[&u8] = &[&3, &2, &4, &1];
+    let values: Vec<_> = cache
+        .get_many(keys.iter().copied())
+        .await
+        .into_iter()
+        .map(|guard| guard.map(|guard| *guard))
+        .collect();
+
+    assert_eq!(values, vec![Some(3), None, None, Some(1)]);
+    assert_eq!(cache.stats().hits(), 2);
+    assert_eq!(cache.stats().misses(), 2);
+}
+
+#[tokio::test]
+async fn test_cache_get_many_cloned_operations() {
+    let cache = Cache::<String, u8>::new();
+
+    cache
+        .insert("one".to_owned(), 1, CacheExpiration::none())
+        .await;
+    cache.insert("two".to_owned(), 2, Instant::now()).await;
+
+    let values = cache.get_many_cloned(vec!["two", "one", "three"]).await;
+
+    assert_eq!(values, vec![None, Some(1), None]);
+}
+
+#[tokio::test]
+async fn test_cache_remove_many_operations() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Instant::now()).await;
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    let removed = cache.remove_many(&[3, 2, 4, 1]).await;
+
+    assert_eq!(removed, vec![Some(3), None, None, Some(1)]);
+    assert!(cache.is_empty().await);
+    assert_eq!(cache.weight(), 0);
+}
+
+#[tokio::test]
+async fn test_cache_from_iter_and_extend_operations() {
+    let cache: Cache<u8, u8> = vec![(1, 2, 60_000), (3, 4, 60_000)].into_iter().collect();
+
+    assert_eq!(cache.len().await, 2);
+    assert_eq!(cache.get(&1).await.unwrap().value(), &2);
+
+    cache
+        .extend(vec![
+            (3, 5, CacheExpiration::none()),
+            (6, 7, CacheExpiration::none()),
+        ])
+        .await;
+
+    assert_eq!(cache.len().await, 3);
+    assert_eq!(cache.get(&3).await.unwrap().value(), &5);
+    assert_eq!(cache.get(&6).await.unwrap().value(), &7);
+}
+
+#[tokio::test]
+async fn test_cache_is_expired_operations() {
+    let cache = Cache::new();
+
+    cache.insert(1, 2, CacheExpiration::none()).await;
+    cache.insert(3, 4, Duration::from_millis(1)).await;
+
+    tokio::time::sleep(Duration::from_millis(5)).await;
+
+    assert_eq!(cache.is_expired(&1).await, Some(false));
+    assert_eq!(cache.is_expired(&3).await, Some(true));
+    assert_eq!(cache.is_expired(&5).await, None);
+    assert_eq!(cache.len().await, 2);
+}
+
+#[tokio::test]
+async fn test_cache_monitor_adaptive() {
+    let cache = Arc::new(Cache::<u8, u8>::new());
+    let clone = cache.clone();
+
+    cache.insert(1, 1, Duration::from_millis(50)).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+
+    let monitor = tokio::spawn(async move {
+        clone
+            .monitor_adaptive(
+                4,
+                0.25,
+                Duration::from_millis(10),
+                Duration::from_millis(40),
+            )
+            .await
+    });
+
+    // wait until the monitor has purged the expired entry
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(cache.len().await, 1);
+
+    monitor.abort();
+}
+
+#[tokio::test]
+async fn test_cache_merge_operations() {
+    let cache = Cache::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+
+    let other = Cache::new();
+
+    other.insert(2, 20, CacheExpiration::none()).await;
+    other.insert(3, 30, Duration::from_secs(60)).await;
+    other.insert(4, 40, Duration::from_millis(1)).await;
+
+    tokio::time::sleep(Duration::from_millis(5)).await;
+
+    assert_eq!(
+        cache
+            .merge(other, |existing, incoming| existing + incoming)
+            .await,
+        2
+    );
+
+    assert_eq!(cache.len().await, 3);
+    assert_eq!(cache.get(&1).await.unwrap().value(), &1);
+    assert_eq!(cache.get(&2).await.unwrap().value(), &22);
+    assert!(cache.ttl(&3).await.unwrap().is_some());
+    assert!(cache.get(&4).await.is_none());
+
+    let other = Cache::new();
+
+    other.insert(1, 10, CacheExpiration::none()).await;
+
+    assert_eq!(cache.merge(other, |existing, _| existing).await, 1);
+    assert_eq!(cache.get(&1).await.unwrap().value(), &1);
+}
+
+#[tokio::test]
+async fn test_cache_merge_expired_and_jitter() {
+    let causes = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let clone = causes.clone();
+
+    let cache = Cache::new()
+        .with_expiry_jitter(Duration::from_secs(60))
+        .with_eviction_listener(move |k, v, cause| clone.lock().unwrap().push((*k, *v, cause)));
+
+    // jitter is applied on insert, so expire the entry explicitly
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.set_expiration(&1, Instant::now()).await;
+
+    let other = Cache::new();
+    let expiration = CacheExpiration::from(Duration::from_secs(60));
+
+    other.insert(1, 10, CacheExpiration::none()).await;
+    other.insert(2, 20, expiration).await;
+
+    tokio::time::sleep(Duration::from_millis(5)).await;
+
+    assert_eq!(cache.merge(other, |_, incoming| incoming).await, 2);
+
+    // expired entries are reported as expired, rather than resolved
+    assert_eq!(
+        *causes.lock().unwrap(),
+        vec![(1, 1, EvictionCause::Expired)]
+    );
+    assert_eq!(cache.get(&1).await.unwrap().value(), &10);
+
+    // merged expirations are carried across exactly, without jitter
+    assert_eq!(cache.get(&2).await.unwrap().expiration(), &expiration);
+}
+
+#[tokio::test]
+async fn test_cache_precise_expiration() {
+    let cache = Cache::new().with_precise_expiration();
+
+    for i in 0..16u8 {
+        cache
+            .insert(i, i, Duration::from_millis(i as u64 * 100))
+            .await;
+    }
+
+    cache.insert(100, 100, CacheExpiration::none()).await;
+    cache.set_expiration(&15, Duration::from_millis(1)).await;
+    cache.remove(&1).await;
+
+    tokio::time::sleep(Duration::from_millis(550)).await;
+
+    // the earliest deadlines are removed exactly, regardless of the sample
+    cache.purge(4, 0.0).await;
+
+    assert_eq!(cache.len().await, 10);
+    assert_eq!(cache.expired().await, 0);
+    assert!(cache.contains_key(&6).await);
+    assert!(!cache.contains_key(&15).await);
+    assert!(cache.contains_key(&100).await);
+}
+
+#[tokio::test]
+async fn test_cache_precise_expiration_paths() {
+    let cache = Cache::new()
+        .with_precise_expiration()
+        .with_touch_on_read(Duration::from_millis(20));
+
+    let expiration = Duration::from_millis(20);
+
+    // every path creating an entry or moving a deadline must be indexed
+    cache
+        .get_or_insert_with(1, expiration, || async { 1 })
+        .await;
+    cache
+        .get_or_try_insert_with(2, expiration, || async { Ok::<_, ()>(2) })
+        .await
+        .unwrap();
+    cache.get_or_load(3, expiration, || async { 3 }).await;
+
+    cache.insert(4, 4, CacheExpiration::none()).await;
+    cache.extend_expiration(&4, expiration).await;
+
+    cache.insert(5, 5, CacheExpiration::none()).await;
+    cache.replace(&5, 5, expiration).await;
+
+    cache.insert(6, 6, Duration::from_secs(60)).await;
+    cache.get(&6).await;
+
+    cache.insert(100, 100, CacheExpiration::none()).await;
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    cache.purge(16, 1.0).await;
+
+    assert_eq!(cache.len().await, 1);
+    assert!(cache.contains_key(&100).await);
+}
+
+#[tokio::test]
+async fn test_cache_precise_expiration_removals() {
+    let cache = Cache::new().with_precise_expiration();
+
+    let before = Duration::from_millis(10);
+    let after = Duration::from_millis(20);
+
+    // every path removing an entry must also remove it from the index
+    cache.insert(0, 0, before).await;
+    cache.pop_first().await;
+    cache.insert(0, 0, after).await;
+
+    cache.insert(1, 1, before).await;
+    cache.remove_if(&1, |_| true).await;
+    cache.insert(1, 1, after).await;
+
+    cache.insert(2, 2, before).await;
+    cache.remove_many([&2]).await;
+    cache.insert(2, 2, after).await;
+
+    cache.insert(3, 3, before).await;
+    cache.insert(3, 3, after).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // only a single deadline should be tracked per key
+    cache.purge(16, 1.0).await;
+
+    assert!(cache.is_empty().await);
+    assert_eq!(cache.last_purge().unwrap().sampled(), 4);
+
+    // including entries evicted to make room in a bounded cache
+    let cache = Cache::new().with_precise_expiration().with_max_capacity(1);
+
+    cache.insert(1, 1, before).await;
+    cache.insert(2, 2, after).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    cache.purge(16, 1.0).await;
+
+    assert!(cache.is_empty().await);
+    assert_eq!(cache.last_purge().unwrap().sampled(), 1);
+}
+
+#[tokio::test]
+async fn test_cache_estimate_operations() {
+    let cache = Cache::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, Duration::from_millis(1)).await;
+    cache.insert(3, 3, Duration::from_millis(1)).await;
+
+    assert_eq!(cache.unexpired_estimate(), 3);
+    assert_eq!(cache.expired_estimate(), 0);
+
+    tokio::time::sleep(Duration::from_millis(5)).await;
+
+    // untouched expired entries are still counted as unexpired
+    assert!(cache.get(&2).await.is_none());
+    assert!(cache.get(&2).await.is_none());
+
+    assert_eq!(cache.unexpired_estimate(), 2);
+    assert_eq!(cache.expired_estimate(), 1);
+
+    cache.remove(&2).await;
+
+    assert_eq!(cache.unexpired_estimate(), 2);
+    assert_eq!(cache.expired_estimate(), 0);
+
+    cache.clear_expired().await;
+
+    assert_eq!(cache.unexpired_estimate(), 1);
+    assert_eq!(cache.expired_estimate(), 0);
+
+    cache.clear().await;
+
+    assert_eq!(cache.unexpired_estimate(), 0);
+}
+
+#[tokio::test]
+async fn test_cache_insert_replace_operations() {
+    let cache = Cache::new();
+
+    assert_eq!(
+        cache.insert_replace(1, 1, CacheExpiration::none()).await,
+        None
+    );
+
+    let (value, expiration) = cache
+        .insert_replace(1, 2, Duration::from_millis(1))
+        .await
+        .unwrap();
+
+    assert_eq!(value, 1);
+    assert_eq!(expiration, CacheExpiration::none());
+
+    tokio::time::sleep(Duration::from_millis(5)).await;
+
+    // expired entries are never returned
+    assert_eq!(
+        cache.insert_replace(1, 3, CacheExpiration::none()).await,
+        None
+    );
+    assert_eq!(cache.get(&1).await.unwrap().value(), &3);
+}
+
+#[tokio::test]
+async fn test_cache_eviction_policy() {
+    struct FirstKey;
+
+    impl EvictionPolicy<u8, u8> for FirstKey {
+        fn select(&self, store: eviction::StoreView<'_, u8, u8>, _sample: usize) -> Vec<u8> {
+            store.iter().take(1).map(|(key, _)| *key).collect()
+        }
+    }
+
+    let causes = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let clone = causes.clone();
+
+    let cache = Cache::new()
+        .with_eviction_policy(FirstKey)
+        .with_eviction_listener(move |k, _, cause| clone.lock().unwrap().push((*k, cause)));
+
+    cache.insert(1, 1, Instant::now()).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    // a single key is selected per batch, so the threshold continues the purge
+    cache.purge(4, 0.25).await;
+
+    assert_eq!(cache.len().await, 0);
+    assert_eq!(
+        *causes.lock().unwrap(),
+        vec![
+            (1, EvictionCause::Expired),
+            (2, EvictionCause::Capacity),
+            (3, EvictionCause::Capacity),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_cache_tinylfu_admission() {
+    let cache = Cache::<u8, u8>::new().with_tinylfu(2);
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+
+    for _ in 0..3 {
+        assert!(cache.get(&1).await.is_some());
+        assert!(cache.get(&2).await.is_some());
+    }
+
+    // a scan of one-off keys should not flush the popular entries
+    for i in 10..20 {
+        cache.insert(i, i, CacheExpiration::none()).await;
+    }
+
+    assert_eq!(cache.len().await, 2);
+    assert!(cache.contains_key(&1).await);
+    assert!(cache.contains_key(&2).await);
+
+    // a key which becomes popular enough should eventually be admitted
+    for _ in 0..5 {
+        cache.insert(30, 30, CacheExpiration::none()).await;
+    }
+
+    // each insertion of an absent key also counts the miss which preceded it
+    assert_eq!(cache.len().await, 2);
+    assert!(cache.contains_key(&30).await);
+    assert_eq!(cache.stats().evictions(), 13);
+}
+
+#[test]
+fn test_cache_read_guard_send() {
+    fn is_send<T: Send>() {}
+
+    // guards only refer to values, so values need only be `Sync`
+    is_send::<CacheReadGuard<'static, std::sync::MutexGuard<'static, ()>>>();
+    is_send::<CacheLoadGuard<'static, Vec<u8>>>();
+}
+
+#[tokio::test]
+async fn test_cache_tinylfu_loader_admission() {
+    let cache = Cache::<u8, u8>::new().with_tinylfu(2);
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+
+    for _ in 0..3 {
+        assert!(cache.get(&1).await.is_some());
+        assert!(cache.get(&2).await.is_some());
+    }
+
+    // rejected loads are still returned, but not stored
+    let loaded = cache
+        .get_or_insert_with(10, CacheExpiration::none(), || async { 10 })
+        .await;
+
+    assert_eq!(*loaded, 10);
+    assert!(!loaded.is_cached());
+
+    let loaded = cache
+        .get_or_load(11, CacheExpiration::none(), || async { 11 })
+        .await;
+
+    assert!(matches!(loaded, CacheLoadGuard::Rejected(11)));
+
+    assert_eq!(cache.keys().await, vec![1, 2]);
+
+    // repeated loads should count towards admission
+    for _ in 0..5 {
+        cache
+            .get_or_insert_with(30, CacheExpiration::none(), || async { 30 })
+            .await;
+    }
+
+    assert_eq!(cache.len().await, 2);
+    assert!(cache.contains_key(&30).await);
+}
+
+#[tokio::test]
+async fn test_cache_wait_for_operations() {
+    let cache = Arc::new(Cache::<u8, u8>::new());
+    let clone = cache.clone();
+
+    let waiter = tokio::spawn(async move {
+        clone
+            .wait_for(&1, Duration::from_secs(5))
+            .await
+            .map(|found| *found)
+    });
+
+    // give the waiter time to start waiting
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    cache.insert(1, 2, CacheExpiration::none()).await;
+
+    assert_eq!(waiter.await.unwrap(), Some(2));
+
+    // present keys are returned immediately, and missing keys time out
+    assert_eq!(
+        cache
+            .wait_for(&1, Duration::from_millis(10))
+            .await
+            .map(|found| *found),
+        Some(2)
+    );
+    assert!(cache
+        .wait_for(&3, Duration::from_millis(10))
+        .await
+        .is_none());
+
+    // entries created by loaders should also wake waiting callers
+    let waiters: Vec<_> = (4..6)
+        .map(|key| {
+            let clone = cache.clone();
+            tokio::spawn(async move {
+                clone
+                    .wait_for(&key, Duration::from_secs(5))
+                    .await
+                    .map(|found| *found)
+            })
+        })
+        .collect();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    cache
+        .get_or_insert_with(4, CacheExpiration::none(), || async { 4 })
+        .await;
+    cache
+        .get_or_load(5, CacheExpiration::none(), || async { 5 })
+        .await;
+
+    for (waiter, expected) in waiters.into_iter().zip(4..6) {
+        assert_eq!(waiter.await.unwrap(), Some(expected));
+    }
+}
+
+#[tokio::test]
+async fn test_cache_compute_operations() {
+    let cache = Cache::<u8, Vec<u8>>::new();
+
+    let append = |value: u8| {
+        move |current: Option<&Vec<u8>>| {
+            let mut values = current.cloned().unwrap_or_default();
+            values.push(value);
+            Some(values)
+        }
+    };
+
+    assert_eq!(
+        cache.compute(1, CacheExpiration::none(), append(1)).await,
+        None
+    );
+    assert_eq!(
+        cache.compute(1, CacheExpiration::none(), append(2)).await,
+        Some(vec![1])
+    );
+    assert_eq!(cache.get(&1).await.unwrap().value(), &vec![1, 2]);
+
+    // returning `None` removes the entry
+    assert_eq!(
+        cache.compute(1, CacheExpiration::none(), |_| None).await,
+        Some(vec![1, 2])
+    );
+    assert!(cache.is_empty().await);
+    assert_eq!(
+        cache.compute(1, CacheExpiration::none(), |_| None).await,
+        None
+    );
+}
+
+#[cfg(feature = "async-channel")]
+#[tokio::test]
+async fn test_cache_expired_channel() {
+    let cache = Cache::new().with_expired_channel(2);
+    let receiver = cache.expired_receiver().unwrap();
+
+    for i in 0..4u8 {
+        cache.insert(i, i, Instant::now()).await;
+    }
+    cache.insert(4, 4, CacheExpiration::none()).await;
+
+    // explicit removals of expired entries are also published
+    assert_eq!(cache.remove(&0).await, None);
+
+    cache.purge(5, 1.0).await;
+
+    assert_eq!(receiver.recv().await.unwrap(), (0, 0));
+    assert_eq!(receiver.recv().await.unwrap(), (1, 1));
+    assert!(receiver.try_recv().is_err());
+
+    // entries beyond the capacity of the channel are dropped
+    assert_eq!(cache.expired_dropped(), 2);
+    assert_eq!(cache.len().await, 1);
+}
+
+#[cfg(feature = "async-channel")]
+#[tokio::test]
+async fn test_cache_expired_channel_paths() {
+    let cache = Cache::new().with_expired_channel(0);
+    let receiver = cache.expired_receiver().unwrap();
+
+    // a zero capacity is clamped to a single entry
+    cache.insert(1, 1, Instant::now()).await;
+    cache.insert(2, 2, Instant::now()).await;
+
+    assert_eq!(cache.clear_expired().await, 2);
+    assert_eq!(receiver.try_recv().unwrap(), (1, 1));
+    assert_eq!(cache.expired_dropped(), 1);
+
+    let cache = Cache::new().with_expired_channel(32);
+    let receiver = cache.expired_receiver().unwrap();
+
+    for i in 0..8u8 {
+        cache.insert(i, i, Instant::now()).await;
+    }
+
+    // every removal of an expired entry should be published
+    cache.remove_many(&[0]).await;
+    cache.remove_range(1..2).await;
+    cache.retain(|_, _| true).await;
+
+    for i in 0..8u8 {
+        cache.insert(i, i, Instant::now()).await;
+    }
+
+    cache.insert(0, 10, CacheExpiration::none()).await;
+    cache.compute(1, CacheExpiration::none(), |_| None).await;
+    cache.pop_last().await;
+
+    cache.insert(7, 7, Instant::now()).await;
+    cache.drain().await;
+
+    let mut published = Vec::new();
+    while let Ok(pair) = receiver.try_recv() {
+        published.push(pair.0);
+    }
+
+    assert_eq!(
+        published,
+        vec![0, 1, 2, 3, 4, 5, 6, 7, 0, 1, 7, 6, 5, 4, 3, 2, 7]
+    );
+}
+
+#[tokio::test]
+async fn test_cache_get_map_operations() {
+    let cache = Cache::new();
+
+    cache
+        .insert(1, vec![1, 2, 3], CacheExpiration::none())
+        .await;
+
+    assert_eq!(cache.get_map(&1, |v| v.len()).await, Some(3));
+    assert_eq!(cache.get_map(&2, |v| v.len()).await, None);
+    assert_eq!(cache.stats().hits(), 1);
+    assert_eq!(cache.stats().misses(), 1);
+}
+
+#[tokio::test]
+async fn test_cache_subscribe_operations() {
+    use futures_lite::StreamExt;
+
+    let cache = Cache::new();
+    let mut events = cache.subscribe();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.update(&1, |v| *v += 1).await;
+    cache.insert(2, 2, Duration::from_millis(0)).await;
+    cache.remove(&1).await;
+    cache.remove(&2).await;
+
+    assert_eq!(events.next().await, Some(CacheEvent::Inserted(1)));
+    assert_eq!(events.next().await, Some(CacheEvent::Updated(1)));
+    assert_eq!(events.next().await, Some(CacheEvent::Inserted(2)));
+    assert_eq!(events.next().await, Some(CacheEvent::Removed(1)));
+    assert_eq!(events.next().await, Some(CacheEvent::Expired(2)));
+
+    // slow subscribers drop events rather than blocking the cache
+    for i in 0..2000 {
+        cache.insert(i, i, CacheExpiration::none()).await;
+    }
+
+    assert_eq!(events.dropped(), 976);
+
+    // dropped subscribers are removed on the next change
+    drop(events);
+    cache.clear().await;
+
+    let mut events = cache.subscribe();
+
+    drop(cache);
+
+    assert_eq!(events.next().await, None);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_cache_subscribe_ordering() {
+    use futures_lite::StreamExt;
+
+    let cache = Arc::new(Cache::new());
+    let mut events = cache.subscribe();
+
+    // race insertions and removals of the same key across threads
+    let tasks: Vec<_> = (0..4)
+        .map(|i| {
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                for _ in 0..100 {
+                    if i % 2 == 0 {
+                        cache.insert(1, i, CacheExpiration::none()).await;
+                    } else {
+                        cache.remove(&1).await;
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let present = cache.contains_key(&1).await;
+    let mut last = None;
+
+    drop(cache);
+
+    while let Some(event) = events.next().await {
+        last = Some(event);
+    }
+
+    // the final event must always match the final state of the cache
+    assert_eq!(events.dropped(), 0);
+    assert_eq!(last == Some(CacheEvent::Inserted(1)), present);
+}
+
+#[tokio::test]
+async fn test_cache_last_purge_report() {
+    let cache = Cache::new();
+
+    assert_eq!(cache.last_purge(), None);
+
+    for i in 0..10 {
+        cache.insert(i, i, Duration::from_millis(0)).await;
+    }
+    cache.insert(10, 10, CacheExpiration::none()).await;
+
+    cache.purge(11, 0.25).await;
+
+    let report = cache.last_purge().unwrap();
+
+    assert_eq!(report.removed(), 10);
+    assert!(report.locked() <= report.elapsed());
+
+    cache.purge(11, 0.25).await;
+
+    assert_eq!(cache.last_purge().unwrap().removed(), 0);
+}
+
+#[tokio::test]
+async fn test_cache_insert_and_remove_hooks() {
+    let gauge = Arc::new(AtomicUsize::new(0));
+    let inserted = gauge.clone();
+    let removed = gauge.clone();
+
+    let cache = Cache::new()
+        .with_max_capacity(2)
+        .with_on_insert(move |_, _| {
+            inserted.fetch_add(1, Ordering::Relaxed);
+        })
+        .with_on_remove(move |_, _| {
+            removed.fetch_sub(1, Ordering::Relaxed);
+        });
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(1, 2, CacheExpiration::none()).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+
+    assert_eq!(gauge.load(Ordering::Relaxed), 2);
+
+    // evictions due to capacity are tracked as removals
+    cache.insert(3, 3, Duration::from_millis(0)).await;
+    assert_eq!(gauge.load(Ordering::Relaxed), cache.len().await);
+
+    cache.purge(5, 0.25).await;
+    assert_eq!(gauge.load(Ordering::Relaxed), cache.len().await);
+
+    cache.remove(&1).await;
+    cache.remove(&2).await;
+    assert_eq!(gauge.load(Ordering::Relaxed), cache.len().await);
+
+    cache.insert(4, 4, CacheExpiration::none()).await;
+    cache.clear().await;
+
+    assert_eq!(gauge.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn test_cache_insert_hook_unlocked() {
+    let handle = Arc::new(std::sync::OnceLock::<std::sync::Weak<Cache<u8, u8>>>::new());
+    let unlocked = Arc::new(AtomicUsize::new(0));
+
+    let cache = Arc::new(Cache::new().with_on_insert({
+        let handle = handle.clone();
+        let unlocked = unlocked.clone();
+        move |_, _| {
+            // key 0 is inserted first, so a miss afterwards means the lock is held
+            let cache = match handle.get().and_then(std::sync::Weak::upgrade) {
+                Some(cache) => cache,
+                None => return,
+            };
+
+            if cache.try_get(&0).is_some() {
+                unlocked.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }));
+
+    cache.insert_nowait(0, 0, CacheExpiration::none()).unwrap();
+    handle.set(Arc::downgrade(&cache)).unwrap();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert_replace(2, 2, CacheExpiration::none()).await;
+    cache
+        .insert_tagged(3, 3, CacheExpiration::none(), vec![])
+        .await;
+    cache
+        .insert_many(vec![(4, 4, CacheExpiration::none())])
+        .await;
+
+    assert_eq!(unlocked.load(Ordering::Relaxed), 4);
+
+    // read-modify-write insertions call the hook under the lock
+    cache
+        .insert_if_absent(5, 5, CacheExpiration::none())
+        .await
+        .unwrap();
+
+    assert_eq!(unlocked.load(Ordering::Relaxed), 4);
+    assert_eq!(cache.len().await, 6);
+}
+
+#[tokio::test]
+async fn test_cache_with_capacity() {
+    let cache = Cache::with_capacity(2);
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+    cache.insert(2, 2, CacheExpiration::none()).await;
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    // the capacity is a hint only, rather than a bound
+    assert_eq!(cache.len().await, 3);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(start_paused = true)]
+async fn test_cache_touch_on_read() {
+    let cache = Arc::new(Cache::<u8, u8>::new().with_touch_on_read(Duration::from_millis(50)));
+    let clone = cache.clone();
+
+    cache.insert(1, 1, Duration::from_millis(50)).await;
+    cache.insert(2, 2, Duration::from_millis(50)).await;
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+    let monitor = tokio::spawn(async move {
+        clone
+            .monitor_with_shutdown(4, 0.25, Duration::from_millis(10), async {
+                rx.await.ok();
+            })
+            .await
+    });
+
+    // keep reading the first key well past the original expiration
+    for _ in 0..10 {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(cache.get(&1).await.is_some());
+    }
+
+    assert!(cache.get(&2).await.is_none());
+    assert_eq!(cache.ttl(&3).await, Some(None));
+
+    // without any reads, the entry then expires as usual
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(cache.len().await, 1);
+
+    tx.send(()).unwrap();
+
+    tokio::time::timeout(Duration::from_secs(1), monitor)
+        .await
+        .expect("monitor should shutdown")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_cache_expiry_jitter() {
+    let cache = Cache::new().with_expiry_jitter(Duration::from_millis(100));
+
+    for i in 0..50 {
+        cache.insert(i, i, Duration::from_millis(100)).await;
+    }
+    cache.insert(50, 50, CacheExpiration::none()).await;
+
+    let mut remaining = Vec::new();
+
+    for i in 0..50 {
+        let ttl = cache.ttl(&i).await.unwrap().unwrap();
+        assert!(ttl <= Duration::from_millis(200));
+        remaining.push(ttl);
+    }
+
+    // entries with the same expiration are spread out
+    remaining.sort();
+    assert!(remaining[49] - remaining[0] > Duration::from_millis(10));
+
+    assert_eq!(cache.ttl(&50).await, Some(None));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(start_paused = true)]
+async fn test_cache_touch_operations() {
+    let cache = Cache::new();
+
+    cache.insert(1, 1, Duration::from_millis(50)).await;
+    cache
+        .insert(
+            2,
+            2,
+            tokio::time::Instant::now().into_std() + Duration::from_millis(50),
+        )
+        .await;
+    cache.insert(3, 3, CacheExpiration::none()).await;
+
+    tokio::time::sleep(Duration::from_millis(30)).await;
+
+    assert!(cache.touch(&1).await);
+    assert!(!cache.touch(&2).await);
+    assert!(!cache.touch(&3).await);
+    assert!(!cache.touch(&4).await);
+
+    tokio::time::sleep(Duration::from_millis(30)).await;
+
+    // only the touched entry has been re-armed
+    assert!(cache.get(&1).await.is_some());
+    assert!(cache.get(&2).await.is_none());
+}
@@ -0,0 +1,104 @@
+use retainer::*;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_builder_default_configuration() {
+    let cache = CacheBuilder::<u8, u8>::new().build().unwrap();
+
+    for i in 0..10 {
+        cache.insert(i, i, CacheExpiration::none()).await;
+    }
+
+    assert_eq!(cache.len().await, 10);
+    assert_eq!(cache.weight(), 10);
+}
+
+#[tokio::test]
+async fn test_builder_configuration() {
+    let evictions = Arc::new(AtomicUsize::new(0));
+    let counter = evictions.clone();
+
+    let cache = Cache::<u8, String>::builder()
+        .label("builder")
+        .max_capacity(3)
+        .max_weight(8)
+        .weigher(|_, value: &String| value.len())
+        .sliding_expiration()
+        .rng_seed(42)
+        .eviction_listener(move |_, _, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+
+    cache
+        .insert(1, "aaaa".to_owned(), Duration::from_millis(100))
+        .await;
+    cache
+        .insert(2, "bbbb".to_owned(), CacheExpiration::none())
+        .await;
+    cache
+        .insert(3, "c".to_owned(), CacheExpiration::none())
+        .await;
+
+    // the weight bound should have evicted the first entry
+    assert_eq!(cache.keys().await, vec![2, 3]);
+    assert_eq!(evictions.load(Ordering::SeqCst), 1);
+
+    cache
+        .insert(4, "d".to_owned(), CacheExpiration::none())
+        .await;
+    cache
+        .insert(5, "e".to_owned(), CacheExpiration::none())
+        .await;
+
+    // and the capacity bound should have evicted the second
+    assert_eq!(cache.keys().await, vec![3, 4, 5]);
+    assert_eq!(evictions.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_builder_validation() {
+    let built = Cache::<u8, u8>::builder().max_capacity(0).build();
+    assert_eq!(built.err(), Some(BuildError::ZeroCapacity));
+
+    let built = Cache::<u8, u8>::builder().tinylfu(0).build();
+    assert_eq!(built.err(), Some(BuildError::ZeroCapacity));
+
+    let built = Cache::<u8, u8>::builder().max_entries(0).build();
+    assert_eq!(built.err(), Some(BuildError::ZeroCapacity));
+
+    let built = Cache::<u8, u8>::builder()
+        .max_weight(0)
+        .weigher(|_, _| 1)
+        .build();
+    assert_eq!(built.err(), Some(BuildError::ZeroWeight));
+
+    let built = Cache::<u8, u8>::builder().max_weight(8).build();
+    assert_eq!(built.err(), Some(BuildError::MissingWeigher));
+
+    let built = Cache::<u8, u8>::builder()
+        .max_capacity(1)
+        .max_weight(8)
+        .weigher(|_, _| 1)
+        .build();
+    assert!(built.is_ok());
+}
+
+#[cfg(feature = "async-channel")]
+#[tokio::test]
+async fn test_builder_expired_channel() {
+    let cache = Cache::<u8, u8>::builder()
+        .expired_channel(4)
+        .build()
+        .unwrap();
+
+    cache.insert(1, 1, std::time::Instant::now()).await;
+    cache.remove(&1).await;
+
+    let receiver = cache.expired_receiver().unwrap();
+    assert_eq!(receiver.recv().await.ok(), Some((1, 1)));
+}
@@ -1,10 +1,11 @@
-use async_std::task;
 use retainer::*;
 use smol::Timer;
 
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+// monitors require a tokio runtime when using tokio timers
+#[cfg(not(feature = "tokio"))]
 #[async_std::test]
 async fn test_async_std() {
     // construct our cache
@@ -12,7 +13,7 @@ async fn test_async_std() {
     let clone = cache.clone();
 
     // spawn the monitor
-    task::spawn(async move {
+    async_std::task::spawn(async move {
         // don't forget to monitor your cache to evict entries
         clone.monitor(25, 0.25, Duration::from_secs(1)).await
     });
@@ -21,6 +22,8 @@ async fn test_async_std() {
     execute_base_test(cache).await
 }
 
+// monitors require a tokio runtime when using tokio timers
+#[cfg(not(feature = "tokio"))]
 #[test]
 fn test_smol() {
     smol::block_on(async {
@@ -75,7 +78,7 @@ async fn execute_base_test(cache: Arc<Cache<&str, usize>>) {
     cache.insert("four", 4, 3500..5000).await;
 
     // insert without expiration (i.e. manual removal)
-    cache.insert("five", 5, CacheExpiration::none()).await;
+    cache.insert_untracked("five", 5).await;
 
     // wait until the monitor has run once
     Timer::after(Duration::from_millis(3250)).await;
@@ -83,11 +86,14 @@ async fn execute_base_test(cache: Arc<Cache<&str, usize>>) {
     // the first two keys should have been removed
     assert!(cache.get(&"one").await.is_none());
     assert!(cache.get(&"two").await.is_none());
+    assert!(!cache.contains_key(&"one").await);
+    assert!(!cache.contains_key(&"two").await);
 
     // the rest should be there still for now
     assert!(cache.get(&"three").await.is_some());
     assert!(cache.get(&"four").await.is_some());
     assert!(cache.get(&"five").await.is_some());
+    assert!(cache.contains_key(&"five").await);
 
     // wait until the monitor has run again
     Timer::after(Duration::from_millis(3250)).await;
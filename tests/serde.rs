@@ -0,0 +1,44 @@
+#![cfg(feature = "serde")]
+use retainer::*;
+
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn test_cache_serde_round_trip() {
+    let cache = Cache::<String, u8>::new();
+
+    cache
+        .insert("one".to_owned(), 1, CacheExpiration::none())
+        .await;
+    cache
+        .insert("two".to_owned(), 2, Duration::from_secs(60))
+        .await;
+    cache.insert("three".to_owned(), 3, Instant::now()).await;
+
+    let json = serde_json::to_string(&cache).unwrap();
+    let restored: Cache<String, u8> = serde_json::from_str(&json).unwrap();
+
+    // expired entries should not be carried across
+    assert_eq!(restored.len().await, 2);
+    assert_eq!(restored.ttl("one").await, Some(None));
+    assert_eq!(restored.get("two").await.unwrap().value(), &2);
+    assert!(restored.ttl("two").await.unwrap().unwrap() > Duration::from_secs(59));
+    assert!(!restored.contains_key("three").await);
+}
+
+#[tokio::test]
+async fn test_cache_serde_locked() {
+    let cache = Cache::<u8, u8>::new();
+
+    cache.insert(1, 1, CacheExpiration::none()).await;
+
+    let guard = cache.get_mut(&1).await.unwrap();
+
+    // serialization should fail rather than wait for the write lock
+    let err = serde_json::to_string(&cache).unwrap_err();
+    assert_eq!(err.to_string(), "cache is locked");
+
+    drop(guard);
+
+    assert_eq!(serde_json::to_string(&cache).unwrap(), "[[1,1,null]]");
+}
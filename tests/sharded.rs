@@ -0,0 +1,71 @@
+use retainer::*;
+
+use std::sync::Arc;
+use std::time::Instant;
+
+#[tokio::test]
+async fn test_sharded_cache_operations() {
+    let cache = ShardedCache::<String, usize>::new(4);
+
+    for i in 0..100 {
+        cache
+            .insert(i.to_string(), i, CacheExpiration::none())
+            .await;
+    }
+
+    assert_eq!(cache.len().await, 100);
+    assert_eq!(cache.get("50").await.unwrap().value(), &50);
+    assert!(cache.contains_key("99").await);
+    assert_eq!(cache.remove("99").await, Some(99));
+    assert!(!cache.contains_key("99").await);
+
+    cache.clear().await;
+
+    assert!(cache.is_empty().await);
+}
+
+#[tokio::test]
+async fn test_sharded_cache_purge() {
+    let cache = ShardedCache::<usize, usize>::new(4);
+
+    for i in 0..100 {
+        cache.insert(i, i, Instant::now()).await;
+    }
+
+    cache.insert_untracked(100, 100).await;
+
+    assert_eq!(cache.expired().await, 100);
+    assert_eq!(cache.unexpired().await, 1);
+
+    cache.purge(101, 0.25).await;
+
+    assert_eq!(cache.len().await, 1);
+    assert!(cache.contains_key(&100).await);
+
+    // very large samples must not overflow when split
+    cache.insert(101, 101, Instant::now()).await;
+    cache.purge(usize::MAX, 0.25).await;
+
+    assert_eq!(cache.len().await, 1);
+}
+
+#[tokio::test]
+async fn test_sharded_cache_concurrent_writes() {
+    let cache = Arc::new(ShardedCache::<usize, usize>::new(8));
+    let mut handles = Vec::new();
+
+    for task in 0..8 {
+        let cache = cache.clone();
+        handles.push(tokio::spawn(async move {
+            for i in 0..100 {
+                cache.insert_untracked(task * 100 + i, i).await;
+            }
+        }));
+    }
+
+    for handle in handles {
+        handle.await.unwrap();
+    }
+
+    assert_eq!(cache.len().await, 800);
+}